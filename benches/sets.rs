@@ -0,0 +1,63 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use stamesp::{interpreter::Interpreter, parser::Symbols};
+
+const TEXT: &str = "Il était une fois, dans un petit village au bord de la mer, une vieille femme qui vendait des coquillages. \
+Chaque matin, elle descendait sur la plage — même quand il pleuvait — et ramassait ce que la marée avait laissé. \
+« Pourquoi te donnes-tu tant de peine ? » lui demandaient les enfants. Elle riait : « Parce que la mer ne ment jamais… » \
+L'hiver venu, les pêcheurs restaient au port ; elle, non. On la voyait, courbée sous le vent, compter ses trouvailles : \
+une conque, deux oursins, trois étoiles… Était-ce de la folie ? Peut-être. Mais qui, au village, aurait osé le lui dire ?! \
+";
+
+// About a megabyte of French prose: a single synthetic paragraph, repeated
+fn corpus() -> String {
+    TEXT.repeat(1_000_000 / TEXT.len() + 1)
+}
+
+fn sets(c: &mut Criterion) {
+
+    let symbols = Symbols::from_file("test.txt", false).expect("Cannot read the file");
+    let interpreter = Interpreter::new(&symbols).unwrap();
+    let compiled = interpreter.compile();
+    let text = corpus();
+
+    let mut group = c.benchmark_group("test.txt");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.sample_size(10);
+
+    group.bench_with_input(BenchmarkId::new("interpreter", text.len()), text.as_str(), |b, text| b.iter(|| interpreter.run(text).unwrap()));
+    group.bench_with_input(BenchmarkId::new("compiled", text.len()), text.as_str(), |b, text| b.iter(|| compiled.run(text).unwrap()));
+    group.bench_with_input(BenchmarkId::new("borrowed", text.len()), text.as_str(), |b, text| b.iter(|| compiled.run_borrowed(text).unwrap()));
+    #[cfg(feature = "rayon")]
+    group.bench_with_input(BenchmarkId::new("parallel", text.len()), text.as_str(), |b, text| b.iter(|| compiled.run_parallel(text, 64 * 1024).unwrap()));
+
+    group.finish();
+}
+
+// Set membership alone, at every character of the text: the values of the set one by one, against the bitmaps, ranges and hashes
+fn lookup(c: &mut Criterion) {
+
+    let symbols = Symbols::from_file("test.txt", false).expect("Cannot read the file");
+    let interpreter = Interpreter::new(&symbols).unwrap();
+    let compiled = interpreter.compile();
+    let text = corpus();
+
+    let positions: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+    let sets: Vec<(&str, u32)> = (0..compiled.set_count() as u32).filter_map(|id| compiled.set_name(id).map(|x| (x, id))).collect();
+
+    let mut group = c.benchmark_group("lookup");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.sample_size(10);
+
+    group.bench_function(BenchmarkId::new("values", sets.len()), |b| b.iter(|| {
+        sets.iter().map(|(name, _)| positions.iter().filter_map(|&pos| interpreter.lookup(name, &text, pos)).count()).sum::<usize>()
+    }));
+    group.bench_function(BenchmarkId::new("compiled", sets.len()), |b| b.iter(|| {
+        sets.iter().map(|&(_, id)| positions.iter().filter_map(|&pos| compiled.lookup(id, &text, pos)).count()).sum::<usize>()
+    }));
+
+    group.finish();
+}
+
+criterion_group!(benches, sets, lookup);
+criterion_main!(benches);
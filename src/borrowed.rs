@@ -0,0 +1,93 @@
+use std::{borrow::Cow, collections::BTreeMap};
+
+use serde::Serialize;
+
+use super::compile::{CompiledState, Tree};
+use super::interpreter::Node;
+
+// Node whose text is a slice of the input, unless it was rewritten or skips part of it
+// Names and attributes are borrowed from the machine
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct BorrowedNode<'a> {
+    pub id: &'a str,
+    pub text: Cow<'a, str>,
+    pub start: usize,
+    pub end: usize,
+    pub attributes: BTreeMap<&'a str, &'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<BorrowedNode<'a>>
+}
+
+impl BorrowedNode<'_> {
+
+    // Owned node, which outlives the input, e.g. to be sent further down a stream
+    pub fn into_owned(self) -> Node {
+        Node {
+            id: self.id.to_string(),
+            text: self.text.into_owned(),
+            start: self.start,
+            end: self.end,
+            attributes: self.attributes.into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            children: self.children.into_iter().map(BorrowedNode::into_owned).collect()
+        }
+    }
+}
+
+impl<'a> From<BorrowedNode<'a>> for Node {
+    fn from(node: BorrowedNode<'a>) -> Self {
+        node.into_owned()
+    }
+}
+
+impl<'a> Tree<'a> for BorrowedNode<'a> {
+
+    fn open(state: &'a CompiledState<'_>, at: usize) -> Self {
+        BorrowedNode {
+            id: &state.label,
+            text: Cow::Borrowed(""),
+            start: at,
+            end: at,
+            attributes: state.attributes.iter().map(|(k, v)| (k.as_ref(), v.as_ref())).collect(),
+            children: Vec::new()
+        }
+    }
+
+    fn empty() -> Self {
+        BorrowedNode { id: "", text: Cow::Borrowed(""), start: 0, end: 0, attributes: BTreeMap::new(), children: Vec::new() }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.text.is_empty() && self.children.is_empty()
+    }
+
+    // Only raw text is read, so there are no nodes to keep as children
+    fn push(&mut self, text: &'a str, at: usize, end: usize, _: &'a [Node], source: Option<&'a str>) {
+
+        if self.is_empty() {
+            self.start = at;
+            self.text = Cow::Borrowed(text);
+            self.end = end;
+            return;
+        }
+
+        // Text read right after the slice so far makes a longer slice
+        let slice = |x: &str, start: usize, end: usize| source.and_then(|s| s.get(start..end)).is_some_and(|s| std::ptr::eq(s, x));
+
+        match (&self.text, source) {
+            (Cow::Borrowed(prev), Some(source)) if self.end == at && slice(prev, self.start, self.end) && slice(text, at, end) => {
+                self.text = Cow::Borrowed(&source[self.start..end]);
+            },
+            _ => self.text.to_mut().push_str(text)
+        }
+        self.end = end;
+    }
+
+    fn extend(&mut self, attributes: &'a [(Cow<'_, str>, Cow<'_, str>)]) {
+        self.attributes.extend(attributes.iter().map(|(k, v)| (k.as_ref(), v.as_ref())));
+    }
+
+    fn close(&mut self, end: usize, children: Vec<Self>) {
+        self.end = end;
+        self.children = children;
+    }
+}
@@ -0,0 +1,859 @@
+use std::{borrow::Cow, cmp::Ordering, collections::{BTreeMap, HashMap}};
+
+use super::borrowed::BorrowedNode;
+use super::interpreter::*;
+use super::parser::*;
+
+pub type StateId = u32;
+pub type SetId = u32;
+
+// Entries of the lookup tables: one per byte the units may start with, and one for units with no text
+const KEYS: usize = 257;
+
+// Text of a compiled machine, owned or borrowed from the bytes it was read from
+pub(crate) type Attributes<'m> = Vec<(Cow<'m, str>, Cow<'m, str>)>;
+
+fn owned(text: Cow<'_, str>) -> Cow<'static, str> {
+    Cow::Owned(text.into_owned())
+}
+
+fn owned_pairs(pairs: Attributes<'_>) -> Attributes<'static> {
+    pairs.into_iter().map(|(k, v)| (owned(k), owned(v))).collect()
+}
+
+// What a transition reads, or a condition looks at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Origin {
+    Set(SetId),
+    Any,
+    Start,
+    End
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct CompiledCondition {
+    pub(crate) negated: bool,
+    pub(crate) sets: Vec<Origin>
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct CompiledTransition<'m> {
+    pub(crate) origin: Origin,
+    pub(crate) target: Option<StateId>, // None for '.'
+    pub(crate) behaviour: WritingBehaviour,
+    pub(crate) stack: StackBehaviour,
+    pub(crate) conditions: Vec<CompiledCondition>,
+    pub(crate) rewrite: Option<Cow<'m, str>>,
+    pub(crate) attributes: Attributes<'m>,
+    pub(crate) keep_node: bool
+}
+
+impl CompiledTransition<'_> {
+
+    fn into_owned(self) -> CompiledTransition<'static> {
+        CompiledTransition {
+            origin: self.origin,
+            target: self.target,
+            behaviour: self.behaviour,
+            stack: self.stack,
+            conditions: self.conditions,
+            rewrite: self.rewrite.map(owned),
+            attributes: owned_pairs(self.attributes),
+            keep_node: self.keep_node
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct CompiledState<'m> {
+    pub(crate) label: Cow<'m, str>, // Name of the nodes written in the state
+    pub(crate) end_point: bool,
+    pub(crate) attributes: Attributes<'m>,
+    pub(crate) call: Option<u32>, // Index in CompiledMachine.machines
+    pub(crate) transitions: Vec<CompiledTransition<'m>>,
+    start: Option<u32>, // Transitions on '^', '$' and '@', by index
+    end: Option<u32>,
+    any: Vec<u32>,
+    skip: Option<Skip>
+}
+
+impl<'m> CompiledState<'m> {
+
+    pub(crate) fn new(label: Cow<'m, str>, end_point: bool, attributes: Attributes<'m>, call: Option<u32>, transitions: Vec<CompiledTransition<'m>>) -> Self {
+        let find = |o: Origin| transitions.iter().position(|x| x.origin == o).map(|x| x as u32);
+        CompiledState {
+            label,
+            end_point,
+            attributes,
+            call,
+            start: find(Origin::Start),
+            end: find(Origin::End),
+            any: transitions.iter().enumerate().filter(|(_, x)| x.origin == Origin::Any).map(|(i, _)| i as u32).collect(),
+            transitions,
+            skip: None
+        }
+    }
+
+    fn into_owned(self) -> CompiledState<'static> {
+        CompiledState {
+            label: owned(self.label),
+            end_point: self.end_point,
+            attributes: owned_pairs(self.attributes),
+            call: self.call,
+            transitions: self.transitions.into_iter().map(CompiledTransition::into_owned).collect(),
+            start: self.start,
+            end: self.end,
+            any: self.any,
+            skip: self.skip
+        }
+    }
+}
+
+// A state looping on itself reads in bulk, up to the next byte that may lead elsewhere
+// Through '@', that is a byte a litteral of its sets starts with; through a set, a byte outside of it
+#[derive(Debug, Clone)]
+pub(crate) struct Skip {
+    write: bool,
+    stops: Vec<u8>,
+    table: Vec<bool>
+}
+
+impl Skip {
+
+    // Number of bytes read before stopping; memchr is used when there are few bytes to stop at
+    fn scan(&self, bytes: &[u8]) -> usize {
+        let found = match self.stops[..] {
+            [] => None,
+            [a] => memchr::memchr(a, bytes),
+            [a, b] => memchr::memchr2(a, b, bytes),
+            [a, b, c] => memchr::memchr3(a, b, c, bytes),
+            _ => bytes.iter().position(|&x| self.table[x as usize])
+        };
+        found.unwrap_or(bytes.len())
+    }
+}
+
+// Longer litterals of a set, sorted and laid out as in .stmc files, so that they can be read in place
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Litterals<'m> {
+    pub(crate) ends: Cow<'m, [u8]>, // Offset in the text where each litteral ends, as little-endian u32
+    pub(crate) text: Cow<'m, str>
+}
+
+impl<'m> Litterals<'m> {
+
+    fn new(mut litterals: Vec<&str>) -> Self {
+        litterals.sort();
+        litterals.dedup();
+        let mut ends = Vec::with_capacity(litterals.len() * 4);
+        let mut text = String::new();
+        for litteral in litterals {
+            text.push_str(litteral);
+            ends.extend((text.len() as u32).to_le_bytes());
+        }
+        Litterals { ends: Cow::Owned(ends), text: Cow::Owned(text) }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.ends.len() / 4
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    fn end(&self, i: usize) -> usize {
+        u32::from_le_bytes(self.ends[i * 4..i * 4 + 4].try_into().unwrap()) as usize
+    }
+
+    pub(crate) fn get(&self, i: usize) -> &str {
+        &self.text[if i == 0 { 0 } else { self.end(i - 1) }..self.end(i)]
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &str> {
+        (0..self.len()).map(|i| self.get(i))
+    }
+
+    pub(crate) fn contains(&self, litteral: &str) -> bool {
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.get(mid).cmp(litteral) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return true
+            }
+        }
+        false
+    }
+
+    fn into_owned(self) -> Litterals<'static> {
+        Litterals { ends: Cow::Owned(self.ends.into_owned()), text: owned(self.text) }
+    }
+}
+
+// Single characters are looked up in a bitmap when they are ASCII, in sorted ranges otherwise; longer litterals are searched in a sorted table
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CompiledSet<'m> {
+    pub(crate) ascii: u128,
+    pub(crate) ranges: Vec<(char, char)>,
+    pub(crate) lengths: Vec<usize>, // Lengths in bytes of the longer litterals, decreasing
+    pub(crate) multi: Litterals<'m>,
+    pub(crate) empty: bool,
+    pub(crate) labels: Vec<Cow<'m, str>>
+}
+
+impl<'m> CompiledSet<'m> {
+
+    pub(crate) fn new(litterals: &[&str], labels: &[String]) -> Self {
+
+        let (mut ascii, mut empty) = (0, false);
+        let mut chars: Vec<char> = Vec::new();
+        let mut multi: Vec<&str> = Vec::new();
+
+        for litteral in litterals {
+            let mut iter = litteral.chars();
+            match (iter.next(), iter.next()) {
+                (None, _) => empty = true,
+                (Some(c), None) if c.is_ascii() => ascii |= 1 << c as u32,
+                (Some(c), None) => chars.push(c),
+                _ => multi.push(litteral)
+            }
+        }
+
+        let mut ranges: Vec<(char, char)> = Vec::new();
+        chars.sort();
+        for c in chars {
+            match ranges.last_mut() {
+                Some((_, end)) if *end as u32 + 1 >= c as u32 => *end = c,
+                _ => ranges.push((c, c))
+            }
+        }
+
+        Self::with(ascii, ranges, empty, Litterals::new(multi), labels.iter().map(|x| Cow::Owned(x.clone())).collect())
+    }
+
+    // Set made of its tables, as they are stored in .stmc files
+    pub(crate) fn with(ascii: u128, ranges: Vec<(char, char)>, empty: bool, multi: Litterals<'m>, labels: Vec<Cow<'m, str>>) -> Self {
+        let mut lengths: Vec<usize> = Vec::new();
+        for litteral in multi.iter() {
+            if !lengths.contains(&litteral.len()) {
+                lengths.push(litteral.len());
+            }
+        }
+        lengths.sort_by(|a, b| b.cmp(a));
+        CompiledSet { ascii, ranges, lengths, multi, empty, labels }
+    }
+
+    fn into_owned(self) -> CompiledSet<'static> {
+        CompiledSet {
+            ascii: self.ascii,
+            ranges: self.ranges,
+            lengths: self.lengths,
+            multi: self.multi.into_owned(),
+            empty: self.empty,
+            labels: self.labels.into_iter().map(owned).collect()
+        }
+    }
+
+    // Bytes the litterals start with
+    fn leads(&self) -> Vec<u8> {
+        let ascii = (0..128u8).filter(|&x| self.ascii & (1 << x) != 0);
+        let chars = self.ranges.iter().flat_map(|&(start, end)| start..=end).map(|c| c.encode_utf8(&mut [0; 4]).as_bytes()[0]);
+        ascii.chain(chars).chain(self.multi.iter().map(|x| x.as_bytes()[0])).collect()
+    }
+
+    pub(crate) fn contains(&self, c: char) -> bool {
+        if c.is_ascii() {
+            self.ascii & (1 << c as u32) != 0
+        }
+        else {
+            self.ranges.binary_search_by(|(start, end)| {
+                if *end < c { Ordering::Less } else if *start > c { Ordering::Greater } else { Ordering::Equal }
+            }).is_ok()
+        }
+    }
+}
+
+// Names of the states and sets, only kept for diagnostics
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Names<'m> {
+    pub(crate) states: Vec<Cow<'m, str>>,
+    pub(crate) sets: Vec<Cow<'m, str>>
+}
+
+// Machine whose states and sets are numbered, each state finding the transitions worth trying from the first byte of the unit read
+#[derive(Debug, Clone)]
+pub struct CompiledMachine<'m> {
+    pub(crate) states: Vec<CompiledState<'m>>,
+    pub(crate) sets: Vec<CompiledSet<'m>>,
+    pub(crate) entry: StateId,
+    // For each state and byte, the list of transitions on sets that may match there, in declaration order
+    lookup: Vec<u32>,
+    candidates: Vec<Vec<u32>>,
+    pub(crate) machines: Vec<CompiledMachine<'m>>,
+    pub(crate) max_depth: usize,
+    pub(crate) names: Names<'m>
+}
+
+// What compiled machines write: owned nodes, or nodes borrowing their text from the input
+pub(crate) trait Tree<'a>: Sized {
+    fn open(state: &'a CompiledState<'_>, at: usize) -> Self;
+    // Placeholder for a node being replaced
+    fn empty() -> Self;
+    fn is_empty(&self) -> bool;
+    // Text read at these offsets, or written in its place; the whole input is given when units are its characters
+    fn push(&mut self, text: &'a str, at: usize, end: usize, read: &'a [Node], source: Option<&'a str>);
+    fn extend(&mut self, attributes: &'a [(Cow<'_, str>, Cow<'_, str>)]);
+    fn close(&mut self, end: usize, children: Vec<Self>);
+}
+
+impl<'a> Tree<'a> for Node {
+
+    fn open(state: &'a CompiledState<'_>, at: usize) -> Self {
+        Node {
+            id: state.label.to_string(),
+            text: String::new(),
+            start: at,
+            end: at,
+            attributes: state.attributes.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            children: Vec::new()
+        }
+    }
+
+    fn empty() -> Self {
+        Node { id: String::new(), text: String::new(), start: 0, end: 0, attributes: BTreeMap::new(), children: Vec::new() }
+    }
+
+    fn is_empty(&self) -> bool {
+        Node::is_empty(self)
+    }
+
+    fn push(&mut self, text: &'a str, at: usize, end: usize, read: &'a [Node], _: Option<&'a str>) {
+        Node::push(self, text, at, end, read);
+    }
+
+    fn extend(&mut self, attributes: &'a [(Cow<'_, str>, Cow<'_, str>)]) {
+        self.attributes.extend(attributes.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+    }
+
+    fn close(&mut self, end: usize, children: Vec<Self>) {
+        self.end = end;
+        self.children = children;
+    }
+}
+
+// Mutable state of a single run over an input, as in the interpreter
+pub(crate) struct CompiledFrame<N> {
+    origin: StateId,
+    container: N,
+    parent: Vec<N>
+}
+
+pub(crate) struct CompiledRun<N> {
+    pub(crate) current: StateId,
+    node: N,
+    pub(crate) nodes: Vec<N>,
+    pub(crate) frames: Vec<CompiledFrame<N>>
+}
+
+impl<'a, N: Tree<'a>> CompiledRun<N> {
+
+    // The node is always replaced after being flushed, so it is moved rather than cloned
+    fn flush(&mut self) {
+        if !self.node.is_empty() {
+            self.nodes.push(std::mem::replace(&mut self.node, N::empty()));
+        }
+    }
+
+    fn close(&mut self, at: usize) -> Option<StateId> {
+        let mut frame = self.frames.pop()?;
+
+        let children = std::mem::replace(&mut self.nodes, frame.parent);
+        frame.container.close(at, children);
+        if !frame.container.is_empty() {
+            self.nodes.push(frame.container);
+        }
+
+        Some(frame.origin)
+    }
+}
+
+impl<'a> Interpreter<'a> {
+
+    // Numbers the states and sets, and builds the lookup tables
+    pub fn compile(&self) -> CompiledMachine<'static> {
+
+        let symbols = self.symbols;
+
+        let mut machines: Vec<&str> = self.machines.keys().copied().collect();
+        machines.sort();
+
+        let mut ids: Vec<&String> = symbols.objects.keys().collect();
+        ids.sort();
+        let states: HashMap<&str, StateId> = ids.iter().enumerate().map(|(i, x)| (x.as_str(), i as StateId)).collect();
+
+        let mut names = Names { states: ids.iter().map(|x| Cow::Owned(x.to_string())).collect(), sets: Vec::new() };
+        let mut sets: Vec<CompiledSet> = Vec::new();
+        let mut set_ids: HashMap<&str, SetId> = HashMap::new();
+
+        let mut origin = |set: &'a str| -> Origin {
+            match set {
+                "@" => Origin::Any,
+                "^" => Origin::Start,
+                "$" => Origin::End,
+                _ => Origin::Set(*set_ids.entry(set).or_insert_with(|| {
+                    let obj = &symbols.objects[set];
+                    let litterals: Vec<&str> = obj.values.iter().map(|&i| symbols.values[i].0.as_str()).collect();
+                    sets.push(CompiledSet::new(&litterals, &obj.labels));
+                    names.sets.push(Cow::Owned(set.to_string()));
+                    (sets.len() - 1) as SetId
+                }))
+            }
+        };
+
+        let mut compiled = Vec::new();
+        for id in ids.iter() {
+            let obj = &symbols.objects[*id];
+
+            let transitions: Vec<CompiledTransition> = obj.transitions.iter().map(|tr| CompiledTransition {
+                origin: origin(&tr.origin),
+                target: if tr.target == "." { None } else { Some(states[tr.target.as_str()]) },
+                behaviour: tr.behaviour,
+                stack: tr.stack,
+                conditions: tr.conditions.iter().map(|x| CompiledCondition { negated: x.negated, sets: x.sets.iter().map(|x| origin(x)).collect() }).collect(),
+                rewrite: tr.rewrite.clone().map(Cow::Owned),
+                attributes: tr.attributes.iter().map(|(k, v)| (Cow::Owned(k.clone()), Cow::Owned(v.clone()))).collect(),
+                keep_node: tr.keep_node
+            }).collect();
+
+            compiled.push(CompiledState::new(
+                Cow::Owned(obj.label().to_string()),
+                (obj.t & StateTypes::EndPoint) != StateTypes::Through,
+                obj.attributes.iter().map(|(k, v)| (Cow::Owned(k.clone()), Cow::Owned(v.clone()))).collect(),
+                obj.call.as_ref().and_then(|x| machines.iter().position(|k| *k == x).map(|x| x as u32)),
+                transitions
+            ));
+        }
+
+        CompiledMachine::build(
+            compiled,
+            sets,
+            states[self.entry.id.as_str()],
+            machines.into_iter().map(|x| self.machines[x].compile()).collect(),
+            self.max_depth,
+            names
+        )
+    }
+}
+
+impl<'m> CompiledMachine<'m> {
+
+    // Builds the lookup tables of the states and finds the ones reading in bulk
+    pub(crate) fn build(mut compiled: Vec<CompiledState<'m>>, sets: Vec<CompiledSet<'m>>, entry: StateId, machines: Vec<CompiledMachine<'m>>, max_depth: usize, names: Names<'m>) -> Self {
+
+        let leads: Vec<Vec<u8>> = sets.iter().map(CompiledSet::leads).collect();
+
+        // Transitions on sets with labels or an empty litteral are tried whatever the unit
+        let mut lookup = Vec::with_capacity(compiled.len() * KEYS);
+        let mut candidates: Vec<Vec<u32>> = Vec::new();
+        let mut known: HashMap<Vec<u32>, u32> = HashMap::new();
+
+        for state in compiled.iter() {
+            for key in 0..KEYS {
+                let list: Vec<u32> = state.transitions.iter().enumerate().filter_map(|(i, tr)| {
+                    let Origin::Set(id) = tr.origin else { return None };
+                    let set = &sets[id as usize];
+                    let starts = key < 256 && leads[id as usize].contains(&(key as u8));
+                    (starts || set.empty || !set.labels.is_empty()).then_some(i as u32)
+                }).collect();
+
+                let index = *known.entry(list).or_insert_with_key(|list| {
+                    candidates.push(list.clone());
+                    (candidates.len() - 1) as u32
+                });
+                lookup.push(index);
+            }
+        }
+
+        for (i, state) in compiled.iter_mut().enumerate() {
+
+            let looping = |tr: &CompiledTransition<'_>| (tr.target.is_none() || (tr.keep_node && tr.target == Some(i as StateId)))
+                && tr.stack == StackBehaviour::Keep && tr.conditions.is_empty() && tr.rewrite.is_none() && tr.attributes.is_empty()
+                && tr.behaviour != WritingBehaviour::WriteBefore;
+            let list = |key: usize| &candidates[lookup[i * KEYS + key] as usize];
+
+            // Transitions tried whatever the unit leave nothing to skip
+            if !list(KEYS - 1).is_empty() {
+                continue;
+            }
+
+            // Bytes the state reads without leaving, and whether it writes them
+            let mut reads: Option<(bool, Vec<bool>)> = None;
+
+            if let Some(tr) = state.any.first().map(|&x| &state.transitions[x as usize]).filter(|x| looping(x)) {
+                reads = Some((tr.behaviour == WritingBehaviour::WriteAfter, (0..256).map(|key| list(key).is_empty()).collect()));
+            }
+
+            // ASCII characters only matched by a set the state loops on, which has no longer litteral starting with them
+            for (t, tr) in state.transitions.iter().enumerate() {
+                let Origin::Set(id) = tr.origin else { continue };
+                let set = &sets[id as usize];
+                let write = tr.behaviour == WritingBehaviour::WriteAfter;
+
+                if !looping(tr) || reads.as_ref().is_some_and(|(w, _)| *w != write) {
+                    continue;
+                }
+                let (_, table) = reads.get_or_insert_with(|| (write, vec![false; 256]));
+                for (key, reads) in table.iter_mut().enumerate().take(128) {
+                    if list(key)[..] == [t as u32] && set.contains(key as u8 as char) && !set.multi.iter().any(|x| x.as_bytes()[0] == key as u8) {
+                        *reads = true;
+                    }
+                }
+            }
+
+            if let Some((write, table)) = reads.filter(|(_, x)| x.iter().any(|x| *x)) {
+                state.skip = Some(Skip {
+                    write,
+                    stops: (0..=255).filter(|&x| !table[x as usize]).collect(),
+                    table: table.iter().map(|x| !x).collect()
+                });
+            }
+        }
+
+        CompiledMachine {
+            entry,
+            states: compiled,
+            sets,
+            lookup,
+            candidates,
+            machines,
+            max_depth,
+            names
+        }
+    }
+
+    // A machine owning its text, which outlives the bytes it was read from
+    pub fn into_owned(self) -> CompiledMachine<'static> {
+        CompiledMachine {
+            states: self.states.into_iter().map(CompiledState::into_owned).collect(),
+            sets: self.sets.into_iter().map(CompiledSet::into_owned).collect(),
+            entry: self.entry,
+            lookup: self.lookup,
+            candidates: self.candidates,
+            machines: self.machines.into_iter().map(CompiledMachine::into_owned).collect(),
+            max_depth: self.max_depth,
+            names: Names { states: self.names.states.into_iter().map(owned).collect(), sets: self.names.sets.into_iter().map(owned).collect() }
+        }
+    }
+
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self.machines = self.machines.into_iter().map(|x| x.max_depth(depth)).collect();
+        self
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn set_count(&self) -> usize {
+        self.sets.len()
+    }
+
+    pub fn state_name(&self, id: StateId) -> Option<&str> {
+        self.names.states.get(id as usize).map(|x| x.as_ref())
+    }
+
+    pub fn set_name(&self, id: SetId) -> Option<&str> {
+        self.names.sets.get(id as usize).map(|x| x.as_ref())
+    }
+
+    pub fn state_id(&self, name: &str) -> Option<StateId> {
+        self.names.states.iter().position(|x| x == name).map(|x| x as StateId)
+    }
+
+    pub fn set_id(&self, name: &str) -> Option<SetId> {
+        self.names.sets.iter().position(|x| x == name).map(|x| x as SetId)
+    }
+
+    // Length of the longest litteral of the set found at the position, as Interpreter::lookup
+    pub fn lookup(&self, set: SetId, input: &str, pos: usize) -> Option<usize> {
+        self.sets.get(set as usize).and_then(|_| self.match_set(Origin::Set(set), input, pos))
+    }
+
+    pub fn run<U: Units + ?Sized>(&self, input: &U) -> Result<Vec<Node>, InterpreterError> {
+        self.execute(input, 0, false).map(|(nodes, _)| nodes)
+    }
+
+    // Same as run, the text of the nodes being borrowed from the input unless it was rewritten or skips part of it
+    pub fn run_borrowed<'a>(&'a self, input: &'a str) -> Result<Vec<BorrowedNode<'a>>, InterpreterError> {
+        self.execute(input, 0, false).map(|(nodes, _)| nodes)
+    }
+
+    fn open<'a, N: Tree<'a>>(&'a self, state: StateId, at: usize) -> N {
+        N::open(&self.states[state as usize], at)
+    }
+
+    fn name(&self, state: StateId) -> String {
+        self.names.states[state as usize].to_string()
+    }
+
+    // Same as Interpreter::execute, returning the nodes and where the machine stopped reading
+    fn execute<'a, U: Units + ?Sized, N: Tree<'a>>(&'a self, input: &'a U, from: usize, called: bool) -> Result<(Vec<N>, usize), InterpreterError> {
+        let mut run = self.start(input, from)?;
+        let pos = self.read(&mut run, input, from, input.size(), called)?;
+        self.finish(run, input, pos)
+    }
+
+    // Part of the input between two offsets where the machine is known to be in its entry state, with nothing left to write
+    // The transitions on '^' and '$' are only taken at the start and the end of the whole input
+    #[cfg_attr(not(feature = "rayon"), allow(dead_code))]
+    pub(crate) fn chunk<'a, N: Tree<'a>>(&'a self, input: &'a str, from: usize, to: usize) -> Result<Vec<N>, InterpreterError> {
+
+        let mut run = if from == 0 {
+            self.start(input, from)?
+        }
+        else {
+            CompiledRun { current: self.entry, node: self.open(self.entry, from), nodes: Vec::new(), frames: Vec::new() }
+        };
+
+        let pos = self.read(&mut run, input, from, to, false)?;
+        if pos == input.size() {
+            return self.finish(run, input, pos).map(|(nodes, _)| nodes);
+        }
+
+        run.flush();
+        Ok(run.nodes)
+    }
+
+    pub(crate) fn start<'a, U: Units + ?Sized, N: Tree<'a>>(&'a self, input: &'a U, from: usize) -> Result<CompiledRun<N>, InterpreterError> {
+
+        let mut run = CompiledRun {
+            current: self.entry,
+            node: self.open::<N>(self.entry, input.start(from)),
+            nodes: Vec::new(),
+            frames: Vec::new()
+        };
+
+        if let Some(i) = self.states[run.current as usize].start {
+            self.apply(&self.states[run.current as usize].transitions[i as usize], &mut run, input, from, 0)?;
+        }
+
+        Ok(run)
+    }
+
+    // Reads units up to the given position, or until no transition matches when called
+    fn read<'a, U: Units + ?Sized, N: Tree<'a>>(&'a self, run: &mut CompiledRun<N>, input: &'a U, from: usize, to: usize, called: bool) -> Result<usize, InterpreterError> {
+        let mut pos = from;
+        while pos < to {
+            match self.step(run, input, pos, to, called)? {
+                Some(next) => pos = next,
+                None => break
+            }
+        }
+        Ok(pos)
+    }
+
+    // Reads a single unit, or a run of them when the state loops on itself, giving the position reached
+    pub(crate) fn step<'a, U: Units + ?Sized, N: Tree<'a>>(&'a self, run: &mut CompiledRun<N>, input: &'a U, pos: usize, to: usize, called: bool) -> Result<Option<usize>, InterpreterError> {
+
+        if let (Some(skip), Some(source)) = (&self.states[run.current as usize].skip, input.source()) {
+            let stop = pos + skip.scan(&source.as_bytes()[pos..to]);
+            if stop > pos {
+                if skip.write {
+                    run.node.push(&source[pos..stop], input.start(pos), input.end(stop), &[], Some(source));
+                }
+                return Ok(Some(stop));
+            }
+        }
+
+        match self.select(run.current, input, pos) {
+            Some((tr, len)) => self.apply(tr, run, input, pos, len).map(Some),
+            None if called => Ok(None),
+            None => {
+                let unit = input.text(pos, input.unit(pos).unwrap_or(0)).to_string();
+                Err(InterpreterError::NoTransition(input.start(pos), self.name(run.current), unit))
+            }
+        }
+    }
+
+    pub(crate) fn finish<'a, U: Units + ?Sized, N: Tree<'a>>(&'a self, mut run: CompiledRun<N>, input: &'a U, pos: usize) -> Result<(Vec<N>, usize), InterpreterError> {
+
+        if pos == input.size() {
+            if let Some(i) = self.states[run.current as usize].end {
+                self.apply(&self.states[run.current as usize].transitions[i as usize], &mut run, input, pos, 0)?;
+            }
+        }
+
+        run.flush();
+        while run.close(input.end(pos)).is_some() {}
+
+        if !self.states[run.current as usize].end_point {
+            return Err(InterpreterError::NotAnEndPoint(input.end(pos), self.name(run.current)));
+        }
+
+        Ok((run.nodes, pos))
+    }
+
+    // Same choice as Interpreter::select, only trying the transitions on sets that have a litteral starting with the byte read
+    fn select<U: Units + ?Sized>(&self, current: StateId, input: &U, pos: usize) -> Option<(&CompiledTransition<'m>, usize)> {
+
+        let state = &self.states[current as usize];
+        let key = input.lead(pos).map_or(KEYS - 1, usize::from);
+
+        let mut best: Option<(&CompiledTransition<'m>, usize)> = None;
+
+        for &i in self.candidates[self.lookup[current as usize * KEYS + key] as usize].iter() {
+            let tr = &state.transitions[i as usize];
+            let len = if tr.conditions.is_empty() {
+                self.match_set(tr.origin, input, pos)
+            }
+            else {
+                self.match_lengths(tr.origin, input, pos).into_iter().find(|&len| self.check(&tr.conditions, input, pos + len))
+            };
+            if let Some(len) = len.filter(|&len| best.is_none_or(|(_, l)| len > l)) {
+                best = Some((tr, len));
+            }
+        }
+
+        if best.is_some() {
+            return best;
+        }
+
+        let len = input.unit(pos)?;
+        state.any.iter()
+            .map(|&i| &state.transitions[i as usize])
+            .find(|x| self.check(&x.conditions, input, pos + len))
+            .map(|tr| (tr, len))
+    }
+
+    fn match_set<U: Units + ?Sized>(&self, origin: Origin, input: &U, pos: usize) -> Option<usize> {
+        match origin {
+            Origin::Any => input.unit(pos),
+            Origin::End => if pos == input.size() { Some(0) } else { None },
+            Origin::Start => None,
+            Origin::Set(set) => {
+                let set = &self.sets[set as usize];
+                let single = input.single(pos).filter(|(c, _)| set.contains(*c)).map(|(_, len)| len);
+                let multi = set.lengths.iter().find_map(|&len| input.slice(pos, len).filter(|x| set.multi.contains(x)).and_then(|x| input.litteral(pos, x)));
+                let empty = if set.empty { input.litteral(pos, "") } else { None };
+                let labels = set.labels.iter().filter_map(|x| input.label(pos, x));
+                single.into_iter().chain(multi).chain(empty).chain(labels).max()
+            }
+        }
+    }
+
+    // Only needed when the longest litteral fails the conditions of the transition
+    fn match_lengths<U: Units + ?Sized>(&self, origin: Origin, input: &U, pos: usize) -> Vec<usize> {
+        let Origin::Set(id) = origin else { return self.match_set(origin, input, pos).into_iter().collect() };
+        let set = &self.sets[id as usize];
+        let single = input.single(pos).filter(|(c, _)| set.contains(*c)).map(|(_, len)| len);
+        let multi = set.lengths.iter().filter_map(|&len| input.slice(pos, len).filter(|x| set.multi.contains(x)).and_then(|x| input.litteral(pos, x)));
+        let empty = if set.empty { input.litteral(pos, "") } else { None };
+        let labels = set.labels.iter().filter_map(|x| input.label(pos, x));
+        let mut out: Vec<usize> = single.into_iter().chain(multi).chain(empty).chain(labels).collect();
+        out.sort_by(|a, b| b.cmp(a));
+        out.dedup();
+        out
+    }
+
+    fn check<U: Units + ?Sized>(&self, conditions: &[CompiledCondition], input: &U, pos: usize) -> bool {
+        conditions.iter().all(|cond| {
+            let mut at = pos;
+            let matched = cond.sets.iter().all(|&set| {
+                match self.match_set(set, input, at) {
+                    Some(len) => { at += len; true },
+                    None => false
+                }
+            });
+            matched != cond.negated
+        })
+    }
+
+    // Same as Interpreter::apply
+    fn apply<'a, U: Units + ?Sized, N: Tree<'a>>(&'a self, tr: &'a CompiledTransition<'m>, run: &mut CompiledRun<N>, input: &'a U, at: usize, len: usize) -> Result<usize, InterpreterError> {
+
+        let text = tr.rewrite.as_deref().unwrap_or(input.text(at, len));
+        let read = if tr.rewrite.is_some() { &[] } else { input.nodes(at, len) };
+        let end = at + len;
+        let (offset, end_offset) = (input.start(at), input.end(end));
+
+        if (tr.target.is_none() || tr.keep_node) && tr.stack == StackBehaviour::Keep {
+            let next = tr.target.unwrap_or(run.current);
+
+            match tr.behaviour {
+                WritingBehaviour::WriteAfter => {
+                    run.node.push(text, offset, end_offset, read, input.source());
+                    run.node.extend(&tr.attributes);
+                    run.current = next;
+                    return Ok(end);
+                },
+                WritingBehaviour::NoWrite => {
+                    run.node.extend(&tr.attributes);
+                    run.current = next;
+                    return Ok(end);
+                },
+                WritingBehaviour::WriteBefore => {}
+            }
+        }
+
+        if tr.behaviour != WritingBehaviour::WriteAfter {
+            if tr.behaviour == WritingBehaviour::WriteBefore {
+                run.node.push(text, offset, end_offset, read, input.source());
+            }
+            run.node.extend(&tr.attributes);
+        }
+        run.flush();
+
+        let start = if tr.behaviour == WritingBehaviour::WriteAfter { at } else { end };
+        let start_offset = if tr.behaviour == WritingBehaviour::WriteAfter { offset } else { input.start(end) };
+
+        run.current = match tr.stack {
+            StackBehaviour::Keep => tr.target.unwrap_or(run.current),
+            StackBehaviour::Push => {
+                let next = tr.target.unwrap_or(run.current);
+
+                if run.frames.len() >= self.max_depth {
+                    return Err(InterpreterError::MaxDepth(offset, self.name(next)));
+                }
+
+                run.frames.push(CompiledFrame {
+                    origin: run.current,
+                    container: self.open(next, start_offset),
+                    parent: std::mem::take(&mut run.nodes)
+                });
+                next
+            },
+            StackBehaviour::Pop => {
+                match run.close(input.end(start)) {
+                    Some(origin) => tr.target.unwrap_or(origin),
+                    None => return Err(InterpreterError::UnbalancedPop(offset, self.name(run.current)))
+                }
+            }
+        };
+
+        run.node = self.open(run.current, start_offset);
+
+        if let Some(machine) = self.states[run.current as usize].call.map(|x| &self.machines[x as usize]) {
+            let (children, stop) = machine.execute(input, start, true)?;
+
+            if stop > start {
+                if tr.behaviour == WritingBehaviour::WriteAfter {
+                    run.node.extend(&tr.attributes);
+                }
+                run.node.close(input.end(stop), children);
+                let next = self.open(run.current, input.start(stop));
+                run.nodes.push(std::mem::replace(&mut run.node, next));
+                return Ok(stop);
+            }
+        }
+
+        if tr.behaviour == WritingBehaviour::WriteAfter {
+            run.node.push(text, offset, end_offset, read, input.source());
+            run.node.extend(&tr.attributes);
+        }
+
+        Ok(end)
+    }
+}
@@ -0,0 +1,162 @@
+use std::{collections::HashMap, fmt::{Display, Write}};
+
+use serde::Serialize;
+
+use super::diff::{describe, on};
+use super::interpreter::*;
+use super::parser::*;
+use super::scanner::Pos;
+
+// What the runs went through, accumulated over as many inputs as needed
+#[derive(Debug, Default)]
+pub struct Coverage {
+    states: HashMap<Pos, usize>,
+    transitions: HashMap<(Pos, usize), usize>, // State and index of the transition, as alternatives share their position
+    litterals: HashMap<(String, String, String), usize> // File, set and text read
+}
+
+impl Observer for Coverage {
+
+    fn enter(&mut self, state: &Object) {
+        *self.states.entry(state.loc.clone()).or_default() += 1;
+    }
+
+    fn fire(&mut self, state: &Object, transition: &Transition, text: &str) {
+        if let Some(i) = state.transitions.iter().position(|x| std::ptr::eq(x, transition)) {
+            *self.transitions.entry((state.loc.clone(), i)).or_default() += 1;
+        }
+        if !PSEUDO_SETS.contains(&transition.origin.as_str()) {
+            *self.litterals.entry((state.loc.file.clone(), transition.origin.clone(), text.to_string())).or_default() += 1;
+        }
+    }
+}
+
+// Part of the rules that no input went through
+#[derive(Debug, Serialize)]
+pub struct Uncovered {
+    pub loc: Pos,
+    pub kind: String,
+    pub what: String
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CoverageReport {
+    // Covered and total counts
+    pub states: (usize, usize),
+    pub transitions: (usize, usize),
+    pub sets: (usize, usize),
+    pub litterals: (usize, usize),
+    pub uncovered: Vec<Uncovered>
+}
+
+impl Display for CoverageReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+
+        for x in self.uncovered.iter() {
+            writeln!(f, "{}{} {} never covered", x.loc, x.kind, x.what)?;
+        }
+
+        let ratio = |(covered, total): (usize, usize)| format!("{}/{}", covered, total);
+        writeln!(f, "states {}, transitions {}, sets {}, litterals {}", ratio(self.states), ratio(self.transitions), ratio(self.sets), ratio(self.litterals))
+    }
+}
+
+impl Coverage {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Everything the machine (and the ones it calls) declares, with how many times it was gone through
+    fn items(&self, symbols: &Symbols, out: &mut Vec<(Uncovered, usize)>) {
+
+        let mut ids: Vec<&String> = symbols.objects.keys().collect();
+        ids.sort();
+
+        let used: Vec<&String> = symbols.objects.values().flat_map(|x| x.transitions.iter().map(|tr| &tr.origin)).collect();
+
+        for obj in ids.into_iter().map(|x| &symbols.objects[x]) {
+
+            if symbols.is_state(obj) {
+                out.push((Uncovered { loc: obj.loc.clone(), kind: String::from("state"), what: format!("'{}'", obj.id) }, self.states.get(&obj.loc).copied().unwrap_or(0)));
+
+                for (i, tr) in obj.transitions.iter().enumerate() {
+                    let hits = self.transitions.get(&(obj.loc.clone(), i)).copied().unwrap_or(0);
+                    out.push((Uncovered { loc: tr.loc.clone(), kind: String::from("transition"), what: format!("'{}: {}{}'", obj.id, on(tr), describe(tr)) }, hits));
+                }
+            }
+
+            // Sets only used in lookahead are never read
+            if (!obj.values.is_empty() || !obj.labels.is_empty()) && used.contains(&&obj.id) {
+
+                for &i in obj.values.iter() {
+                    let (value, loc) = &symbols.values[i];
+                    let hits = self.litterals.get(&(obj.loc.file.clone(), obj.id.clone(), value.clone())).copied().unwrap_or(0);
+                    out.push((Uncovered { loc: loc.clone(), kind: String::from("litteral"), what: format!("{:?} of '{}'", value, obj.id) }, hits));
+                }
+
+                // Labels have no position of their own, reading them counts for the set only
+                let hits = self.litterals.iter().filter(|((file, set, _), _)| *file == obj.loc.file && *set == obj.id).map(|(_, n)| n).sum();
+                out.push((Uncovered { loc: obj.loc.clone(), kind: String::from("set"), what: format!("'{}'", obj.id) }, hits));
+            }
+        }
+
+        for machine in symbols.machines.values() {
+            self.items(machine, out);
+        }
+    }
+
+    pub fn report(&self, symbols: &Symbols) -> CoverageReport {
+
+        let mut items = Vec::new();
+        self.items(symbols, &mut items);
+
+        let mut report = CoverageReport::default();
+
+        for (item, hits) in items {
+            let count = match item.kind.as_str() {
+                "state" => &mut report.states,
+                "transition" => &mut report.transitions,
+                "set" => &mut report.sets,
+                _ => &mut report.litterals
+            };
+            count.1 += 1;
+            if hits > 0 {
+                count.0 += 1;
+            }
+            else {
+                report.uncovered.push(item);
+            }
+        }
+
+        report.uncovered.sort_by(|a, b| (&a.loc.file, a.loc.line, a.loc.column).cmp(&(&b.loc.file, b.loc.line, b.loc.column)));
+        report
+    }
+
+    // The rule file with the number of times each line was gone through, '#####' marking the lines with something never covered
+    pub fn annotate(&self, symbols: &Symbols, file: &str, source: &str) -> String {
+
+        let mut items = Vec::new();
+        self.items(symbols, &mut items);
+
+        let mut out = String::new();
+
+        for (n, line) in source.lines().enumerate() {
+
+            let here: Vec<&(Uncovered, usize)> = items.iter().filter(|(x, _)| x.loc.file == file && x.loc.line == n + 1).collect();
+            let never: Vec<&str> = here.iter().filter(|(_, hits)| *hits == 0).map(|(x, _)| x.what.as_str()).collect();
+
+            if here.is_empty() {
+                writeln!(out, "{:>7} | {}", "", line).unwrap();
+            }
+            else if never.is_empty() {
+                writeln!(out, "{:>7} | {}", here.iter().map(|(_, hits)| hits).max().unwrap(), line).unwrap();
+            }
+            else {
+                writeln!(out, "{:>7} | {}    <-- never covered: {}", "#####", line, never.join(", ")).unwrap();
+            }
+        }
+
+        out
+    }
+}
@@ -0,0 +1,284 @@
+use std::io::{ErrorKind, Read};
+
+use super::compile::*;
+use super::interpreter::*;
+
+// Bytes read from a stream at once, and read text kept before being dropped
+const CHUNK: usize = 8192;
+
+// A run of a compiled machine over an input, giving the nodes as soon as they are written
+// The machine is only read, so that it can be shared between threads (e.g. in an Arc) with a cursor per input
+pub struct Cursor<'a, U: Units + ?Sized> {
+    machine: &'a CompiledMachine<'a>,
+    input: &'a U,
+    run: Option<CompiledRun<Node>>, // None once the input is read or an error was met
+    pending: std::vec::IntoIter<Node>,
+    error: Option<InterpreterError>,
+    pos: usize
+}
+
+impl CompiledMachine<'_> {
+
+    pub fn cursor<'a, U: Units + ?Sized>(&'a self, input: &'a U) -> Cursor<'a, U> {
+        let (run, error) = match self.start(input, 0) {
+            Ok(run) => (Some(run), None),
+            Err(e) => (None, Some(e))
+        };
+        Cursor { machine: self, input, run, pending: Vec::new().into_iter(), error, pos: 0 }
+    }
+}
+
+impl<U: Units + ?Sized> Cursor<'_, U> {
+
+    // Position of the next unit to read
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    // Current state, until the input is read
+    pub fn state(&self) -> Option<&str> {
+        self.run.as_ref().and_then(|run| self.machine.state_name(run.current))
+    }
+}
+
+impl<U: Units + ?Sized> Iterator for Cursor<'_, U> {
+    type Item = Result<Node, InterpreterError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(node) = self.pending.next() {
+                return Some(Ok(node));
+            }
+
+            let Some(run) = self.run.as_mut() else { return self.error.take().map(Err) };
+
+            // Nodes written inside a level are only given once it is closed
+            if run.frames.is_empty() && !run.nodes.is_empty() {
+                self.pending = std::mem::take(&mut run.nodes).into_iter();
+                continue;
+            }
+
+            let result = if self.pos < self.input.size() {
+                self.machine.step(run, self.input, self.pos, self.input.size(), false).map(|x| self.pos = x.unwrap_or(self.pos))
+            }
+            else {
+                let run = self.run.take().unwrap();
+                self.machine.finish(run, self.input, self.pos).map(|(nodes, _)| self.pending = nodes.into_iter())
+            };
+
+            if let Err(e) = result {
+                self.run = None;
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+// Part of a text read from a stream, positions being in the part and offsets in the whole text
+struct Window<'a> {
+    text: &'a str,
+    base: usize
+}
+
+impl Units for Window<'_> {
+
+    fn size(&self) -> usize {
+        self.text.size()
+    }
+
+    fn unit(&self, pos: usize) -> Option<usize> {
+        self.text.unit(pos)
+    }
+
+    fn litteral(&self, pos: usize, litteral: &str) -> Option<usize> {
+        self.text.litteral(pos, litteral)
+    }
+
+    fn label(&self, pos: usize, label: &str) -> Option<usize> {
+        self.text.label(pos, label)
+    }
+
+    fn text(&self, pos: usize, len: usize) -> &str {
+        self.text.text(pos, len)
+    }
+
+    fn lead(&self, pos: usize) -> Option<u8> {
+        self.text.lead(pos)
+    }
+
+    fn single(&self, pos: usize) -> Option<(char, usize)> {
+        self.text.single(pos)
+    }
+
+    fn slice(&self, pos: usize, len: usize) -> Option<&str> {
+        self.text.slice(pos, len)
+    }
+
+    fn source(&self) -> Option<&str> {
+        Some(self.text)
+    }
+
+    fn nodes(&self, _: usize, _: usize) -> &[Node] {
+        &[]
+    }
+
+    fn start(&self, pos: usize) -> usize {
+        self.base + pos
+    }
+
+    fn end(&self, pos: usize) -> usize {
+        self.base + pos
+    }
+}
+
+// A run of a compiled machine over text read from a stream, giving the nodes as a cursor does
+// Text is read ahead as far as the litterals and conditions of a step may look, and dropped once read
+pub struct Stream<'a, R: Read> {
+    machine: &'a CompiledMachine<'a>,
+    reader: R,
+    text: String, // Text read and not dropped yet, starting at the offset base of the whole text
+    partial: Vec<u8>, // Start of a character cut by the last read
+    base: usize,
+    pos: usize,
+    ahead: Option<usize>, // None when a called machine may read any amount of text
+    done: bool, // The end of the stream was reached
+    run: Option<CompiledRun<Node>>,
+    started: bool,
+    pending: std::vec::IntoIter<Node>
+}
+
+impl CompiledMachine<'_> {
+
+    pub fn stream<R: Read>(&self, reader: R) -> Stream<'_, R> {
+        Stream {
+            machine: self,
+            reader,
+            text: String::new(),
+            partial: Vec::new(),
+            base: 0,
+            pos: 0,
+            ahead: self.ahead(),
+            done: false,
+            run: None,
+            started: false,
+            pending: Vec::new().into_iter()
+        }
+    }
+
+    // Bytes a step may look at past its position: a set reads at most its longest litteral or a character, '@' a character
+    fn ahead(&self) -> Option<usize> {
+
+        if self.states.iter().any(|x| x.call.is_some()) {
+            return None;
+        }
+
+        let width = |origin: &Origin| match origin {
+            Origin::Set(id) => self.sets[*id as usize].lengths.first().map_or(4, |&x| x.max(4)),
+            Origin::Any => 4,
+            Origin::Start | Origin::End => 0
+        };
+
+        self.states.iter().flat_map(|x| x.transitions.iter()).map(|tr| {
+            width(&tr.origin) + tr.conditions.iter().map(|x| x.sets.iter().map(width).sum::<usize>()).max().unwrap_or(0)
+        }).max().or(Some(0))
+    }
+}
+
+impl<R: Read> Stream<'_, R> {
+
+    // Offset of the next byte to read in the whole text
+    pub fn position(&self) -> usize {
+        self.base + self.pos
+    }
+
+    // Current state, until the stream is read
+    pub fn state(&self) -> Option<&str> {
+        self.run.as_ref().and_then(|run| self.machine.state_name(run.current))
+    }
+
+    // Reads until more than the given number of bytes are there past the position, or the stream ends
+    fn fill(&mut self, ahead: Option<usize>) -> Result<(), InterpreterError> {
+
+        let mut chunk = [0; CHUNK];
+
+        while !self.done && ahead.is_none_or(|x| self.text.len() - self.pos <= x) {
+            let at = self.base + self.text.len();
+            let read = match self.reader.read(&mut chunk) {
+                Ok(read) => read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(InterpreterError::Unreadable(at, e.to_string()))
+            };
+
+            self.done = read == 0;
+            self.partial.extend(&chunk[..read]);
+
+            let valid = match std::str::from_utf8(&self.partial) {
+                Ok(_) => self.partial.len(),
+                Err(e) if e.error_len().is_none() && !self.done => e.valid_up_to(),
+                Err(e) => return Err(InterpreterError::Unreadable(at + e.valid_up_to(), String::from("Invalid UTF-8 text")))
+            };
+            self.text.push_str(std::str::from_utf8(&self.partial[..valid]).unwrap());
+            self.partial.drain(..valid);
+        }
+
+        Ok(())
+    }
+
+    fn advance(&mut self) -> Result<(), InterpreterError> {
+
+        if !self.started {
+            self.started = true;
+            self.fill(self.ahead)?;
+            let window = Window { text: &self.text, base: self.base };
+            self.run = Some(self.machine.start(&window, 0)?);
+            return Ok(());
+        }
+
+        self.fill(self.ahead)?;
+
+        let window = Window { text: &self.text, base: self.base };
+        if self.pos < self.text.len() {
+            let run = self.run.as_mut().unwrap();
+            self.pos = self.machine.step(run, &window, self.pos, self.text.len(), false)?.unwrap_or(self.pos);
+        }
+        else {
+            let run = self.run.take().unwrap();
+            self.pending = self.machine.finish(run, &window, self.pos)?.0.into_iter();
+        }
+
+        if self.pos >= CHUNK {
+            self.text.drain(..self.pos);
+            self.base += self.pos;
+            self.pos = 0;
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for Stream<'_, R> {
+    type Item = Result<Node, InterpreterError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(node) = self.pending.next() {
+                return Some(Ok(node));
+            }
+
+            match self.run.as_mut() {
+                // Nodes written inside a level are only given once it is closed
+                Some(run) if run.frames.is_empty() && !run.nodes.is_empty() => {
+                    self.pending = std::mem::take(&mut run.nodes).into_iter();
+                    continue;
+                },
+                None if self.started => return None,
+                _ => {}
+            }
+
+            if let Err(e) = self.advance() {
+                self.run = None;
+                return Some(Err(e));
+            }
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::{collections::HashMap, error::Error};
+
+use super::interpreter::Interpreter;
+use super::parser::*;
+
+// What a state reads: a litteral, the label of a node from a previous machine, or a pseudo-set
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Symbol {
+    Litteral(String),
+    Label(String),
+    Pseudo(&'static str)
+}
+
+impl Symbols {
+
+    // Transitions of a single state taken when reading the symbol
+    pub(crate) fn matching<'s>(&'s self, member: &'s Object, symbol: &Symbol) -> Vec<&'s Transition> {
+
+        let direct: Vec<&Transition> = member.transitions.iter().filter(|tr| match symbol {
+            Symbol::Pseudo(set) => tr.origin == *set,
+            Symbol::Litteral(value) => self.objects.get(&tr.origin).is_some_and(|set| set.values.iter().any(|&i| self.values[i].0 == *value)),
+            Symbol::Label(label) => self.objects.get(&tr.origin).is_some_and(|set| set.labels.contains(label))
+        }).collect();
+
+        // '@' reads a single character (or node) that is not in the state's sets
+        let single = match symbol {
+            Symbol::Litteral(value) => value.chars().count() == 1,
+            Symbol::Label(_) => true,
+            Symbol::Pseudo(_) => false
+        };
+
+        if direct.is_empty() && single {
+            member.transitions.iter().filter(|tr| tr.origin == "@").collect()
+        }
+        else {
+            direct
+        }
+    }
+
+    // Builds an equivalent machine with at most one transition per state for any token, using the subset construction
+    // States are only merged when every run they stand for writes the same nodes, and the merged state keeps track of them
+    pub fn determinize(&self) -> Result<Symbols, Box<dyn Error>> {
+
+        for obj in self.objects.values() {
+            if obj.call.is_some() {
+                return Err(Box::new(ParserError::NotDeterminizable(obj.loc.clone(), format!("call in '{}'", obj.id))));
+            }
+            for tr in obj.transitions.iter() {
+                if !tr.conditions.is_empty() {
+                    return Err(Box::new(ParserError::NotDeterminizable(tr.loc.clone(), format!("lookahead on '{}'", tr.origin))));
+                }
+                if tr.stack != StackBehaviour::Keep {
+                    return Err(Box::new(ParserError::NotDeterminizable(tr.loc.clone(), format!("push or pop on '{}'", tr.origin))));
+                }
+            }
+        }
+
+        let entry = Interpreter::new(self)?.entry;
+
+        let mut out = Symbols {
+            objects: HashMap::new(),
+            values: self.values.clone(),
+            errors: Vec::new(),
+            machines: HashMap::new(),
+            tests: self.tests.clone()
+        };
+
+        let mut names: HashMap<Vec<String>, String> = HashMap::new();
+        let mut queue = vec![vec![entry.id.clone()]];
+        names.insert(queue[0].clone(), entry.id.clone());
+
+        while let Some(subset) = queue.pop() {
+
+            let members: Vec<&Object> = subset.iter().map(|x| &self.objects[x]).collect();
+            let name = names[&subset].clone();
+
+            let mut alphabet = vec![Symbol::Pseudo("^"), Symbol::Pseudo("$"), Symbol::Pseudo("@")];
+            let mut indices: HashMap<&str, usize> = HashMap::new();
+
+            for tr in members.iter().flat_map(|x| x.transitions.iter()) {
+                if let Some(set) = self.objects.get(&tr.origin) {
+                    for &i in set.values.iter() {
+                        let symbol = Symbol::Litteral(self.values[i].0.clone());
+                        if !alphabet.contains(&symbol) {
+                            indices.insert(&self.values[i].0, i);
+                            alphabet.push(symbol);
+                        }
+                    }
+                    for label in set.labels.iter() {
+                        let symbol = Symbol::Label(label.clone());
+                        if !alphabet.contains(&symbol) {
+                            alphabet.push(symbol);
+                        }
+                    }
+                }
+            }
+
+            let mut transitions: Vec<(Transition, Vec<Symbol>)> = Vec::new();
+
+            for symbol in alphabet {
+
+                let matches: Vec<(&Object, &Transition)> = members.iter()
+                    .flat_map(|m| self.matching(m, &symbol).into_iter().map(move |tr| (*m, tr)))
+                    .collect();
+
+                let Some(&(first, primary)) = matches.first() else {
+                    continue;
+                };
+
+                // The runs merged must all write the token the same way, into nodes of the same name, as the merged state can only follow one of them
+                let output = |m: &'_ Object, tr: &'_ Transition| {
+                    let stays = tr.target == "." && tr.behaviour != WritingBehaviour::WriteBefore;
+                    let target = if tr.target == "." { Some(m) } else { self.objects.get(&tr.target) };
+                    let node = target.filter(|_| !stays).map(|x| (x.label().to_string(), x.attributes.clone()));
+                    (stays, node, tr.behaviour, tr.rewrite.clone(), tr.attributes.clone())
+                };
+                if let Some(&(_, tr)) = matches.iter().find(|&&(m, tr)| output(m, tr) != output(first, primary)) {
+                    let read = match &symbol {
+                        Symbol::Litteral(value) | Symbol::Label(value) => value.as_str(),
+                        Symbol::Pseudo(set) => set
+                    };
+                    return Err(Box::new(ParserError::NotDeterminizable(tr.loc.clone(), format!("choice between different outputs on '{}'", read))));
+                }
+
+                let mut targets: Vec<String> = Vec::new();
+                for (m, tr) in matches.iter() {
+                    let target = if tr.target == "." { &m.id } else { &tr.target };
+                    if !targets.contains(target) {
+                        targets.push(target.clone());
+                    }
+                }
+
+                // Staying in the state keeps the node open, even if the merged state changes
+                let stays = primary.target == "." && primary.behaviour != WritingBehaviour::WriteBefore;
+
+                let target = if stays && targets == subset {
+                    String::from(".")
+                }
+                else {
+                    names.entry(targets.clone()).or_insert_with(|| {
+                        queue.push(targets.clone());
+                        targets.join("|")
+                    }).clone()
+                };
+
+                let tr = Transition {
+                    loc: primary.loc.clone(),
+                    origin: String::new(),
+                    keep_node: stays && target != ".",
+                    target,
+                    behaviour: primary.behaviour,
+                    stack: StackBehaviour::Keep,
+                    conditions: Vec::new(),
+                    rewrite: primary.rewrite.clone(),
+                    attributes: primary.attributes.clone()
+                };
+
+                // Symbols leading to the same transition share a set
+                let group = transitions.iter_mut().find(|(x, symbols)| {
+                    !matches!(symbols[0], Symbol::Pseudo(_)) && !matches!(symbol, Symbol::Pseudo(_))
+                        && x.target == tr.target && x.behaviour == tr.behaviour && x.keep_node == tr.keep_node
+                        && x.rewrite == tr.rewrite && x.attributes == tr.attributes
+                });
+
+                match group {
+                    Some((_, symbols)) => symbols.push(symbol),
+                    None => transitions.push((tr, vec![symbol]))
+                }
+            }
+
+            let mut obj = Object {
+                values: Vec::new(),
+                labels: Vec::new(),
+                id: name.clone(),
+                transitions: Vec::new(),
+                loc: members[0].loc.clone(),
+                t: StateTypes::Through,
+                attributes: members[0].attributes.clone(),
+                call: None,
+                origins: members.iter().map(|x| (x.id.clone(), x.loc.clone())).collect(),
+                label: members[0].label.clone()
+            };
+
+            if subset.len() == 1 && subset[0] == entry.id {
+                obj.t = StateTypes::EntryPoint;
+            }
+            if members.iter().any(|x| (x.t & StateTypes::EndPoint) != StateTypes::Through) {
+                obj.t = obj.t | StateTypes::EndPoint;
+            }
+
+            for (n, (mut tr, symbols)) in transitions.into_iter().enumerate() {
+
+                if let Symbol::Pseudo(set) = symbols[0] {
+                    tr.origin = set.to_string();
+                }
+                else {
+                    let set = format!("{}/{}", name, n);
+                    let mut values = Vec::new();
+                    let mut labels = Vec::new();
+
+                    for symbol in symbols {
+                        match symbol {
+                            Symbol::Litteral(value) => values.push(indices[value.as_str()]),
+                            Symbol::Label(label) => labels.push(label),
+                            Symbol::Pseudo(_) => {}
+                        }
+                    }
+
+                    out.objects.insert(set.clone(), Object {
+                        values,
+                        labels,
+                        id: set.clone(),
+                        transitions: Vec::new(),
+                        loc: tr.loc.clone(),
+                        t: StateTypes::Through,
+                        attributes: Vec::new(),
+                        call: None,
+                        origins: Vec::new(),
+                        label: None
+                    });
+                    tr.origin = set;
+                }
+
+                obj.transitions.push(tr);
+            }
+
+            out.objects.insert(name, obj);
+        }
+
+        Ok(out)
+    }
+}
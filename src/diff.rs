@@ -0,0 +1,180 @@
+use std::{collections::BTreeSet, fmt::{Display, Write}};
+
+use serde::Serialize;
+
+use super::parser::*;
+
+// A single difference between two symbol tables, regardless of how the rule files were written
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    StateAdded { state: String },
+    StateRemoved { state: String },
+    StateChanged { state: String, property: String, old: String, new: String },
+    TransitionAdded { state: String, on: String, transition: String },
+    TransitionRemoved { state: String, on: String, transition: String },
+    TransitionChanged { state: String, on: String, old: String, new: String },
+    SetAdded { set: String },
+    SetRemoved { set: String },
+    LitteralAdded { set: String, litteral: String },
+    LitteralRemoved { set: String, litteral: String },
+    LabelAdded { set: String, label: String },
+    LabelRemoved { set: String, label: String }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+
+        match self {
+            Change::StateAdded { state } => writeln!(f, "+ state '{}'", state),
+            Change::StateRemoved { state } => writeln!(f, "- state '{}'", state),
+            Change::StateChanged { state, property, old, new } => writeln!(f, "~ state '{}': {} was {}, now {}", state, property, old, new),
+            Change::TransitionAdded { state, on, transition } => writeln!(f, "+ '{}' on {}{}", state, on, transition),
+            Change::TransitionRemoved { state, on, transition } => writeln!(f, "- '{}' on {}{}", state, on, transition),
+            Change::TransitionChanged { state, on, old, new } => writeln!(f, "~ '{}' on {}{}, now{}", state, on, old, new),
+            Change::SetAdded { set } => writeln!(f, "+ set '{}'", set),
+            Change::SetRemoved { set } => writeln!(f, "- set '{}'", set),
+            Change::LitteralAdded { set, litteral } => writeln!(f, "+ {:?} in '{}'", litteral, set),
+            Change::LitteralRemoved { set, litteral } => writeln!(f, "- {:?} in '{}'", litteral, set),
+            Change::LabelAdded { set, label } => writeln!(f, "+ label '{}' in '{}'", label, set),
+            Change::LabelRemoved { set, label } => writeln!(f, "- label '{}' in '{}'", label, set)
+        }
+    }
+}
+
+// What a transition reads, as written in the rule file
+pub(crate) fn on(tr: &Transition) -> String {
+    let mut out = tr.origin.clone();
+    for cond in tr.conditions.iter() {
+        write!(out, " {}({})", if cond.negated { '!' } else { '&' }, cond.sets.join(" ")).unwrap();
+    }
+    out
+}
+
+// What a transition does, as written in the rule file
+pub(crate) fn describe(tr: &Transition) -> String {
+    let mut out = String::new();
+    if let Some(text) = &tr.rewrite {
+        write!(out, " -> {:?}", text).unwrap();
+    }
+    write!(out, " = {}{}", tr.modifier(), tr.target).unwrap();
+    if !tr.attributes.is_empty() {
+        let attributes: Vec<String> = tr.attributes.iter().map(|(k, v)| format!("{}={:?}", k, v)).collect();
+        write!(out, " [{}]", attributes.join(" ")).unwrap();
+    }
+    out
+}
+
+fn kind(obj: &Object) -> String {
+    let entry = (obj.t & StateTypes::EntryPoint) != StateTypes::Through;
+    let end = (obj.t & StateTypes::EndPoint) != StateTypes::Through;
+    String::from(match (entry, end) {
+        (true, true) => "entry and end point",
+        (true, false) => "entry point",
+        (false, true) => "end point",
+        (false, false) => "through"
+    })
+}
+
+// Transitions grouped by what they read, in declaration order
+fn groups(obj: Option<&Object>) -> Vec<(String, Vec<String>)> {
+    let mut out: Vec<(String, Vec<String>)> = Vec::new();
+    for tr in obj.iter().flat_map(|x| x.transitions.iter()) {
+        let key = on(tr);
+        match out.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, descriptions)) => descriptions.push(describe(tr)),
+            None => out.push((key, vec![describe(tr)]))
+        }
+    }
+    out
+}
+
+impl Symbols {
+
+    pub(crate) fn is_state(&self, obj: &Object) -> bool {
+        !obj.transitions.is_empty() || obj.t != StateTypes::Through || obj.call.is_some()
+            || (obj.values.is_empty() && obj.labels.is_empty())
+            || self.objects.values().any(|x| x.transitions.iter().any(|tr| tr.target == obj.id))
+    }
+
+    fn litterals(&self, obj: &Object) -> Vec<String> {
+        obj.values.iter().map(|&i| self.values[i].0.clone()).collect()
+    }
+
+    // States, transitions and sets that differ from the old table to the new one, by name
+    pub fn diff(&self, new: &Symbols) -> Vec<Change> {
+
+        let mut changes = Vec::new();
+        let names: BTreeSet<&String> = self.objects.keys().chain(new.objects.keys()).collect();
+
+        for name in names {
+
+            let (a, b) = (self.objects.get(name), new.objects.get(name));
+            let (state_a, state_b) = (a.filter(|x| self.is_state(x)), b.filter(|x| new.is_state(x)));
+            let (set_a, set_b) = (a.filter(|x| !x.values.is_empty() || !x.labels.is_empty()), b.filter(|x| !x.values.is_empty() || !x.labels.is_empty()));
+
+            match (state_a, state_b) {
+                (None, Some(_)) => changes.push(Change::StateAdded { state: name.clone() }),
+                (Some(_), None) => changes.push(Change::StateRemoved { state: name.clone() }),
+                (Some(x), Some(y)) => {
+                    let properties = [
+                        ("type", kind(x), kind(y)),
+                        ("attributes", format!("{:?}", x.attributes), format!("{:?}", y.attributes)),
+                        ("call", format!("{:?}", x.call), format!("{:?}", y.call))
+                    ];
+                    for (property, old, new) in properties {
+                        if old != new {
+                            changes.push(Change::StateChanged { state: name.clone(), property: property.to_string(), old, new });
+                        }
+                    }
+                },
+                (None, None) => {}
+            }
+
+            let (old_groups, new_groups) = (groups(state_a), groups(state_b));
+            let keys = new_groups.iter().map(|x| &x.0).chain(old_groups.iter().map(|x| &x.0).filter(|k| !new_groups.iter().any(|x| x.0 == **k)));
+
+            for key in keys {
+                let find = |groups: &Vec<(String, Vec<String>)>| groups.iter().find(|x| x.0 == *key).map_or(Vec::new(), |x| x.1.clone());
+                let (old, new) = (find(&old_groups), find(&new_groups));
+
+                if let ([old], [new]) = (&old[..], &new[..]) {
+                    if old != new {
+                        changes.push(Change::TransitionChanged { state: name.clone(), on: key.clone(), old: old.clone(), new: new.clone() });
+                    }
+                    continue;
+                }
+                for transition in old.iter().filter(|x| !new.contains(x)) {
+                    changes.push(Change::TransitionRemoved { state: name.clone(), on: key.clone(), transition: transition.clone() });
+                }
+                for transition in new.iter().filter(|x| !old.contains(x)) {
+                    changes.push(Change::TransitionAdded { state: name.clone(), on: key.clone(), transition: transition.clone() });
+                }
+            }
+
+            match (set_a, set_b) {
+                (None, Some(_)) => changes.push(Change::SetAdded { set: name.clone() }),
+                (Some(_), None) => changes.push(Change::SetRemoved { set: name.clone() }),
+                _ => {}
+            }
+
+            let (old, new_values) = (set_a.map_or(Vec::new(), |x| self.litterals(x)), set_b.map_or(Vec::new(), |x| new.litterals(x)));
+            for litteral in old.iter().filter(|x| !new_values.contains(x)) {
+                changes.push(Change::LitteralRemoved { set: name.clone(), litteral: litteral.clone() });
+            }
+            for litteral in new_values.iter().filter(|x| !old.contains(x)) {
+                changes.push(Change::LitteralAdded { set: name.clone(), litteral: litteral.clone() });
+            }
+
+            let (old, new_labels) = (set_a.map_or(Vec::new(), |x| x.labels.clone()), set_b.map_or(Vec::new(), |x| x.labels.clone()));
+            for label in old.iter().filter(|x| !new_labels.contains(x)) {
+                changes.push(Change::LabelRemoved { set: name.clone(), label: label.clone() });
+            }
+            for label in new_labels.iter().filter(|x| !old.contains(x)) {
+                changes.push(Change::LabelAdded { set: name.clone(), label: label.clone() });
+            }
+        }
+
+        changes
+    }
+}
@@ -0,0 +1,227 @@
+use std::{collections::{BTreeMap, HashSet, VecDeque}, error::Error, fmt::Display};
+
+use super::interpreter::*;
+use super::parser::*;
+
+// Whether some accepted input ends in a given state
+#[derive(Debug, PartialEq)]
+pub enum Reach {
+    Input(String), // Shortest one
+    Never,
+    // None was found on inputs up to this length, but longer ones were not all explored
+    Unknown(usize)
+}
+
+impl Display for Reach {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reach::Input(input) => writeln!(f, "{:?}", input),
+            Reach::Never => writeln!(f, "No input is ever accepted there"),
+            Reach::Unknown(length) => writeln!(f, "No input of up to {} characters is accepted there, longer inputs were not all explored", length)
+        }
+    }
+}
+
+// Accepted input, with the nodes written for it
+pub type Sample = (String, Vec<Node>);
+
+// Small xorshift generator, so that samples can be reproduced from a seed
+struct Random(u64);
+
+impl Random {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+// Tokens inputs are made of: the litterals of the machines, their characters, and one character standing for '@'
+pub(crate) fn alphabet(machines: &[&Symbols]) -> Vec<String> {
+
+    let mut tokens: Vec<String> = Vec::new();
+    for (value, _) in machines.iter().flat_map(|x| x.values.iter()) {
+        for token in std::iter::once(value.clone()).chain(value.chars().map(String::from)) {
+            if !token.is_empty() && !tokens.contains(&token) {
+                tokens.push(token);
+            }
+        }
+    }
+    if let Some(unknown) = ['x', 'a', '0', '#', '~', '\u{e000}'].into_iter().find(|c| !tokens.iter().any(|x| x.contains(*c))) {
+        tokens.push(unknown.to_string());
+    }
+
+    tokens.sort_by_key(|x| (x.chars().count(), x.clone()));
+    tokens
+}
+
+// A token and the lookahead after it are decided within this many bytes
+pub(crate) fn window(tokens: &[String]) -> usize {
+    2 * tokens.iter().map(String::len).max().unwrap_or(1)
+}
+
+// Where the machine stands, as far as longer inputs are concerned
+pub(crate) fn standing(progress: &Option<(Run, usize)>, input: &str) -> String {
+    match progress {
+        Some((run, pos)) => format!("{} {:?} {:?}", run.current.id, run.frames.iter().map(|x| &x.origin.id).collect::<Vec<_>>(), &input[*pos..]),
+        None => String::from("failed")
+    }
+}
+
+impl<'a> Interpreter<'a> {
+
+    // Called machines read as far as they can, so the inputs cannot be explored piecewise
+    pub(crate) fn explorable(&self) -> Result<(), Box<dyn Error>> {
+        match self.symbols.objects.values().find(|x| x.call.is_some()) {
+            Some(obj) => Err(Box::new(ParserError::NotExplorable(obj.loc.clone(), format!("call in '{}'", obj.id)))),
+            None => Ok(())
+        }
+    }
+
+    // Runs the machine as far as the end of the input cannot change its choices anymore
+    // Returns nothing when the machine fails in this part of the input, as it would for any longer input
+    pub(crate) fn progress(&self, input: &str, window: usize) -> Option<(Run<'a>, usize)> {
+
+        let mut run = Run {
+            current: self.entry,
+            node: Node::new(self.entry, 0),
+            nodes: Vec::new(),
+            frames: Vec::new()
+        };
+
+        if let Some(tr) = run.current.transitions.iter().find(|x| x.origin == "^") {
+            self.apply(tr, &mut run, input, 0, 0, &mut ()).ok()?;
+        }
+
+        let mut pos = 0;
+        while pos + window <= input.len() {
+            let (tr, len) = self.select(run.current, input, pos)?;
+            pos = self.apply(tr, &mut run, input, pos, len, &mut ()).ok()?;
+        }
+
+        Some((run, pos))
+    }
+
+    // Every accepted input of up to this many characters, with its nodes, shortest first
+    pub fn enumerate(&self, max_length: usize) -> Result<Vec<Sample>, Box<dyn Error>> {
+
+        self.explorable()?;
+
+        let tokens = alphabet(&[self.symbols]);
+        let window = window(&tokens);
+
+        let mut out = Vec::new();
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut stack = vec![String::new()];
+
+        while let Some(input) = stack.pop() {
+
+            if !seen.insert(input.clone()) || self.progress(&input, window).is_none() {
+                continue;
+            }
+            if let Ok(nodes) = self.run(input.as_str()) {
+                out.push((input.clone(), nodes));
+            }
+
+            let length = input.chars().count();
+            for token in tokens.iter().filter(|x| length + x.chars().count() <= max_length) {
+                stack.push(input.clone() + token);
+            }
+        }
+
+        out.sort_by(|a, b| (a.0.chars().count(), &a.0).cmp(&(b.0.chars().count(), &b.0)));
+        Ok(out)
+    }
+
+    // Accepted inputs of up to this many characters picked at random, with their nodes
+    pub fn sample(&self, count: usize, max_length: usize, seed: u64) -> Result<Vec<Sample>, Box<dyn Error>> {
+
+        self.explorable()?;
+
+        let tokens = alphabet(&[self.symbols]);
+        let window = window(&tokens);
+        let mut random = Random(seed ^ 0x9e37_79b9_7f4a_7c15);
+
+        let mut out: Vec<Sample> = Vec::new();
+
+        for _ in 0..count * 100 {
+            if out.len() == count {
+                break;
+            }
+
+            let mut input = String::new();
+            let target = random.below(max_length + 1);
+
+            loop {
+                let length = input.chars().count();
+                let accepted = self.run(input.as_str()).ok();
+
+                // Tokens the machine does not fail on
+                let next: Vec<&String> = tokens.iter()
+                    .filter(|x| length + x.chars().count() <= max_length && self.progress(&(input.clone() + x), window).is_some())
+                    .collect();
+
+                if (length >= target && accepted.is_some()) || next.is_empty() {
+                    if let Some(nodes) = accepted.filter(|_| !out.iter().any(|x| x.0 == input)) {
+                        out.push((input, nodes));
+                    }
+                    break;
+                }
+                input.push_str(next[random.below(next.len())]);
+            }
+        }
+
+        Ok(out)
+    }
+
+    // Shortest accepted input ending in the state, or in any state
+    // Inputs are no longer extended once the machine stands where a shorter input already led it, so the search usually ends with an answer
+    pub fn reach(&self, end: Option<&str>, max_length: usize) -> Result<Reach, Box<dyn Error>> {
+
+        self.explorable()?;
+
+        if let Some(end) = end.filter(|x| !self.symbols.objects.contains_key(*x)) {
+            return Err(Box::new(InterpreterError::UndefinedIdentifier(end.to_string())));
+        }
+
+        let tokens = alphabet(&[self.symbols]);
+        let window = window(&tokens);
+
+        let mut queue: BTreeMap<usize, VecDeque<String>> = BTreeMap::new();
+        queue.entry(0).or_default().push_back(String::new());
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut complete = true;
+
+        while let Some(mut entry) = queue.first_entry() {
+
+            let Some(input) = entry.get_mut().pop_front() else {
+                entry.remove();
+                continue;
+            };
+
+            if let Ok((_, _, state)) = self.execute(input.as_str(), 0, false, &mut ()) {
+                if end.is_none_or(|x| x == state.id) {
+                    return Ok(Reach::Input(input));
+                }
+            }
+
+            let progress = self.progress(&input, window);
+            if progress.is_none() || !seen.insert(standing(&progress, &input)) {
+                continue;
+            }
+
+            let length = input.chars().count();
+            for token in tokens.iter() {
+                let next = length + token.chars().count();
+                if next > max_length {
+                    complete = false;
+                    continue;
+                }
+                queue.entry(next).or_default().push_back(input.clone() + token);
+            }
+        }
+
+        Ok(if complete { Reach::Never } else { Reach::Unknown(max_length) })
+    }
+}
@@ -0,0 +1,153 @@
+use std::{collections::{BTreeMap, HashSet, VecDeque}, error::Error, fmt::Display};
+
+use super::enumerate::{alphabet, standing, window};
+use super::export;
+use super::interpreter::*;
+
+// Input on which two machines do not write the same nodes, with what each of them wrote
+#[derive(Debug)]
+pub struct Counterexample {
+    pub input: String,
+    pub old: Result<Vec<Node>, String>,
+    pub new: Result<Vec<Node>, String>
+}
+
+#[derive(Debug)]
+pub enum Verdict {
+    Equivalent,
+    Different(Counterexample),
+    // No difference was found on inputs up to this length, but longer ones were not all covered
+    Undecided(usize)
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+
+        let output = |x: &Result<Vec<Node>, String>| match x {
+            Ok(nodes) => export::to_text(nodes),
+            Err(e) => e.clone()
+        };
+
+        match self {
+            Verdict::Equivalent => writeln!(f, "The machines write the same nodes for every input"),
+            Verdict::Undecided(length) => writeln!(f, "No difference found on inputs of up to {} characters, longer inputs were not all explored", length),
+            Verdict::Different(example) => {
+                writeln!(f, "The machines differ on the input {:?}", example.input)?;
+                write!(f, "--- old\n{}--- new\n{}", output(&example.old), output(&example.new))
+            }
+        }
+    }
+}
+
+// Node as seen from the end of the input read so far, so that prefixes of different lengths can be compared
+fn relative(node: &Node, at: usize) -> String {
+    format!("{}{:?}{:?}{:?}{}:{}", node.id, node.text, node.attributes, node.children, at - node.start.min(at), at - node.end.min(at))
+}
+
+// Nodes with everything in them, for the runs whose levels cannot be compared piecewise
+fn absolute(run: &Run) -> String {
+    let frames: Vec<_> = run.frames.iter().map(|x| (x.origin.id.as_str(), &x.container, &x.parent)).collect();
+    format!("{:?}{:?}{:?}", frames, run.nodes, run.node)
+}
+
+// Nodes written so far, telling whether they are closed
+fn sequence<'r>(run: &'r Run) -> Vec<(&'r Node, bool)> {
+    let mut out: Vec<(&Node, bool)> = run.nodes.iter().map(|x| (x, true)).collect();
+    if !run.node.is_empty() {
+        out.push((&run.node, false));
+    }
+    out
+}
+
+impl<'a> Interpreter<'a> {
+
+    // Compares two machines on every input, exploring inputs by increasing length until they behave the same way for any continuation
+    // The tokens tried are the litterals of both machines, their characters, and one character they do not know
+    pub fn equivalent(&self, other: &Interpreter<'a>, max_length: usize) -> Result<Verdict, Box<dyn Error>> {
+
+        self.explorable()?;
+        other.explorable()?;
+
+        let tokens = alphabet(&[self.symbols, other.symbols]);
+        let window = window(&tokens);
+
+        let mut queue: BTreeMap<usize, VecDeque<String>> = BTreeMap::new();
+        queue.entry(0).or_default().push_back(String::new());
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut complete = true;
+
+        while let Some(mut entry) = queue.first_entry() {
+
+            let Some(input) = entry.get_mut().pop_front() else {
+                entry.remove();
+                continue;
+            };
+
+            let old = self.run(input.as_str()).map_err(|e| e.to_string());
+            let new = other.run(input.as_str()).map_err(|e| e.to_string());
+            if old.as_ref().ok() != new.as_ref().ok() {
+                return Ok(Verdict::Different(Counterexample { input, old, new }));
+            }
+
+            if !seen.insert(self.signature(other, &input, window)) {
+                continue;
+            }
+
+            let length = input.chars().count();
+            for token in tokens.iter() {
+                let next = length + token.chars().count();
+                if next > max_length {
+                    complete = false;
+                    continue;
+                }
+                queue.entry(next).or_default().push_back(input.clone() + token);
+            }
+        }
+
+        Ok(if complete { Verdict::Equivalent } else { Verdict::Undecided(max_length) })
+    }
+
+    // Two inputs with the same signature lead both machines to write the same way on any continuation
+    fn signature(&self, other: &Interpreter<'a>, input: &str, window: usize) -> String {
+
+        let end = input.len();
+        let (a, b) = (self.progress(input, window), other.progress(input, window));
+
+        let state = |x: &Option<(Run, usize)>| standing(x, input);
+
+        let (Some((ra, _)), Some((rb, _))) = (&a, &b) else {
+            return format!("{} | {}", state(&a), state(&b));
+        };
+
+        if !ra.frames.is_empty() || !rb.frames.is_empty() {
+            return format!("{} | {} | {} | {}", state(&a), state(&b), absolute(ra), absolute(rb));
+        }
+
+        // Nodes written by both machines are left out, as well as the text of a node they both started the same way
+        let (sa, sb) = (sequence(ra), sequence(rb));
+
+        let common = sa.iter().zip(sb.iter()).take_while(|(x, y)| x.1 && y.1 && x.0 == y.0).count();
+        let (mut sa, mut sb) = (&sa[common..], &sb[common..]);
+
+        let mut pending = String::new();
+        if let (Some((x, closed_x)), Some((y, closed_y))) = (sa.first(), sb.first()) {
+
+            // Whatever comes next, the outputs differ unless one of the machines fails
+            if *closed_x && *closed_y {
+                return format!("{} | {} | diverged", state(&a), state(&b));
+            }
+
+            let same = x.id == y.id && x.start == y.start && x.text == y.text && x.attributes == y.attributes && x.children == y.children;
+            if same {
+                pending = format!("{} {} {} {}", closed_x, closed_y, end - x.end, end - y.end);
+                sa = &sa[1..];
+                sb = &sb[1..];
+            }
+        }
+
+        let residue = |nodes: &[(&Node, bool)]| nodes.iter().map(|(x, closed)| format!("{}{}", closed, relative(x, end))).collect::<Vec<_>>();
+
+        format!("{} | {} | {} {:?} {:?} | {:?} {:?} {:?} {:?}", state(&a), state(&b), pending,
+            residue(sa), residue(sb), ra.node.id, ra.node.attributes, rb.node.id, rb.node.attributes)
+    }
+}
@@ -0,0 +1,143 @@
+use std::fmt::Write;
+
+use super::parser::*;
+use super::interpreter::Node;
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn write_xml(out: &mut String, node: &Node, depth: usize) {
+
+    let indent = "    ".repeat(depth);
+
+    write!(out, "{}<node id=\"{}\" start=\"{}\" end=\"{}\"", indent, escape_xml(&node.id), node.start, node.end).unwrap();
+    for (key, value) in node.attributes.iter() {
+        write!(out, " {}=\"{}\"", key, escape_xml(value)).unwrap();
+    }
+
+    if node.children.is_empty() {
+        writeln!(out, ">{}</node>", escape_xml(&node.text)).unwrap();
+    }
+    else {
+        out.push_str(">\n");
+        for child in node.children.iter() {
+            write_xml(out, child, depth + 1);
+        }
+        writeln!(out, "{}</node>", indent).unwrap();
+    }
+}
+
+pub fn to_xml(nodes: &[Node]) -> String {
+
+    let mut out = String::from("<document>\n");
+
+    for node in nodes.iter() {
+        write_xml(&mut out, node, 1);
+    }
+
+    out.push_str("</document>\n");
+    out
+}
+
+fn write_text(out: &mut String, node: &Node, depth: usize) {
+
+    writeln!(out, "{}{}\t{:?}", "    ".repeat(depth), node.id, node.text).unwrap();
+    for child in node.children.iter() {
+        write_text(out, child, depth + 1);
+    }
+}
+
+// One node per line, children being indented under their parent
+pub fn to_text(nodes: &[Node]) -> String {
+
+    let mut out = String::new();
+
+    for node in nodes.iter() {
+        write_text(&mut out, node, 0);
+    }
+
+    out
+}
+
+pub fn to_json(nodes: &[Node]) -> String {
+    serde_json::to_string_pretty(nodes).unwrap()
+}
+
+impl Transition {
+
+    // Modifiers as written before the target
+    pub(crate) fn modifier(&self) -> String {
+        match self.behaviour {
+            WritingBehaviour::WriteAfter => "",
+            WritingBehaviour::WriteBefore => "%",
+            WritingBehaviour::NoWrite => "^"
+        }.to_string() + match self.stack {
+            StackBehaviour::Keep => "",
+            StackBehaviour::Push => ">",
+            StackBehaviour::Pop => "<"
+        }
+    }
+}
+
+impl Symbols {
+
+    // Graphviz representation of the machine, one node per state and one edge per transition
+    pub fn to_dot(&self) -> String {
+
+        let mut out = String::from("digraph {\n    rankdir=LR;\n");
+
+        let mut ids: Vec<&String> = self.objects.keys().collect();
+        ids.sort();
+
+        let targets: Vec<&String> = self.objects.values()
+            .flat_map(|x| x.transitions.iter().map(|tr| &tr.target))
+            .collect();
+
+        for id in ids.iter() {
+            let obj = &self.objects[*id];
+
+            if obj.transitions.is_empty() && obj.t == StateTypes::Through && !targets.contains(id) {
+                continue;
+            }
+
+            let shape = if (obj.t & StateTypes::EndPoint) != StateTypes::Through { "doublecircle" } else { "circle" };
+            match &obj.call {
+                Some(file) => writeln!(out, "    {:?} [shape={}, label={:?}];", obj.id, shape, format!("{}\ncall {}", obj.id, file)).unwrap(),
+                None => writeln!(out, "    {:?} [shape={}];", obj.id, shape).unwrap()
+            }
+
+            if (obj.t & StateTypes::EntryPoint) != StateTypes::Through {
+                writeln!(out, "    \"\" [shape=none];\n    \"\" -> {:?};", obj.id).unwrap();
+            }
+        }
+
+        for id in ids.iter() {
+            let obj = &self.objects[*id];
+
+            for tr in obj.transitions.iter() {
+                let target = if tr.target == "." { &obj.id } else { &tr.target };
+                let modifier = tr.modifier();
+
+                let mut label = tr.origin.clone();
+                for cond in tr.conditions.iter() {
+                    write!(label, " {}({})", if cond.negated { '!' } else { '&' }, cond.sets.join(" ")).unwrap();
+                }
+                if let Some(text) = &tr.rewrite {
+                    write!(label, " -> {:?}", text).unwrap();
+                }
+                if !modifier.is_empty() || tr.target == "." {
+                    write!(label, " / {}{}", modifier, tr.target).unwrap();
+                }
+
+                writeln!(out, "    {:?} -> {:?} [label={:?}];", obj.id, target, label).unwrap();
+            }
+        }
+
+        out.push_str("}\n");
+        out
+    }
+}
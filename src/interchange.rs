@@ -0,0 +1,286 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}};
+
+use serde::{Serialize, Deserialize};
+
+use super::parser::*;
+use super::scanner::Pos;
+
+// Version of the schema described in the README, written in every document
+pub const SCHEMA_VERSION: u32 = 1;
+
+// A machine as other tools write it: the objects of a rule file, with the same meaning as in the DSL
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Document {
+    version: u32,
+    objects: Vec<Entry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tests: Vec<Example>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    id: String,
+    #[serde(default, skip_serializing_if = "is_false")]
+    entry: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    end: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>, // Name of the nodes, when it is not the id
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    litterals: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    labels: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    attributes: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    call: Option<String>, // Rule file, relative to this one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    transitions: Vec<Step>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Step {
+    on: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    conditions: Vec<Lookahead>,
+    target: String,
+    #[serde(default = "write_after", skip_serializing_if = "is_write_after")]
+    writing: WritingBehaviour,
+    #[serde(default = "keep", skip_serializing_if = "is_keep")]
+    stack: StackBehaviour,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rewrite: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    attributes: BTreeMap<String, String>,
+    // Another target for the same token as the previous transition, as written with '|'
+    #[serde(default, skip_serializing_if = "is_false")]
+    alternative: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    keep_node: bool
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Lookahead {
+    #[serde(default, skip_serializing_if = "is_false")]
+    negated: bool,
+    sets: Vec<String>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Example {
+    input: String,
+    expected: Vec<String>
+}
+
+fn is_false(x: &bool) -> bool {
+    !x
+}
+
+fn write_after() -> WritingBehaviour {
+    WritingBehaviour::WriteAfter
+}
+
+fn is_write_after(x: &WritingBehaviour) -> bool {
+    *x == WritingBehaviour::WriteAfter
+}
+
+fn keep() -> StackBehaviour {
+    StackBehaviour::Keep
+}
+
+fn is_keep(x: &StackBehaviour) -> bool {
+    *x == StackBehaviour::Keep
+}
+
+impl Symbols {
+
+    // Objects have no line in a document: their position is their index in 'objects', and the index of the transition as the column
+    fn from_document(document: Document, name: &str, verbose: bool, callers: &mut Vec<PathBuf>) -> Self {
+
+        let mut symbols = Self::empty();
+        let at = |line: usize, column: usize| Pos { file: name.to_string(), line, column };
+
+        if document.version != SCHEMA_VERSION {
+            symbols.errors.push(Box::new(ParserError::Malformed(at(0, 0), format!("Schema version {}, while version {} is expected", document.version, SCHEMA_VERSION))));
+            return symbols;
+        }
+
+        for (i, entry) in document.objects.into_iter().enumerate() {
+
+            let loc = at(i + 1, 0);
+            if symbols.objects.contains_key(&entry.id) {
+                symbols.errors.push(Box::new(ParserError::Malformed(loc, format!("Object '{}' is declared twice", entry.id))));
+                continue;
+            }
+
+            let mut t = StateTypes::Through;
+            if entry.entry {
+                t = t | StateTypes::EntryPoint;
+            }
+            if entry.end {
+                t = t | StateTypes::EndPoint;
+            }
+
+            let mut values = Vec::new();
+            for litteral in entry.litterals {
+                symbols.values.push((litteral, loc.clone()));
+                values.push(symbols.values.len() - 1);
+            }
+
+            let call = entry.call.and_then(|file| symbols.load(&loc, &file, verbose, callers));
+
+            if entry.transitions.iter().flat_map(|x| x.conditions.iter()).any(|x| x.sets.iter().any(|x| x == "^")) {
+                symbols.errors.push(Box::new(ParserError::StartAhead(loc.clone())));
+            }
+
+            // Names of attributes are checked as in the DSL
+            let keys = entry.attributes.keys().chain(entry.transitions.iter().flat_map(|x| x.attributes.keys()));
+            for key in keys.filter(|x| !is_attribute_name(x)) {
+                symbols.errors.push(Box::new(ParserError::InvalidAttribute(loc.clone(), key.clone())));
+            }
+
+            // Alternatives share the position of the first one, as in the DSL
+            let mut transitions: Vec<Transition> = Vec::new();
+            let mut group = 0;
+
+            for (j, step) in entry.transitions.into_iter().enumerate() {
+
+                let conditions: Vec<Condition> = step.conditions.into_iter().map(|x| Condition { negated: x.negated, sets: x.sets }).collect();
+                let previous = transitions.last().filter(|x| x.origin == step.on && x.conditions == conditions && x.rewrite == step.rewrite);
+
+                let loc = match (step.alternative, previous) {
+                    (true, Some(previous)) => previous.loc.clone(),
+                    (true, None) => {
+                        symbols.errors.push(Box::new(ParserError::NotAttached(at(i + 1, j + 1), String::from("'alternative'"))));
+                        continue;
+                    },
+                    (false, _) => {
+                        group = transitions.len();
+                        at(i + 1, j + 1)
+                    }
+                };
+
+                for before in transitions[..group].iter().filter(|x| x.origin == step.on && x.conditions == conditions) {
+                    symbols.errors.push(Box::new(ParserError::DuplicateTransition(loc.clone(), format!("{} => {} ({}already defined here)", step.on, step.target, before.loc))));
+                }
+
+                transitions.push(Transition {
+                    loc,
+                    origin: step.on,
+                    target: step.target,
+                    behaviour: step.writing,
+                    stack: step.stack,
+                    conditions,
+                    rewrite: step.rewrite,
+                    attributes: step.attributes.into_iter().collect(),
+                    keep_node: step.keep_node
+                });
+            }
+
+            symbols.objects.insert(entry.id.clone(), Object {
+                values,
+                labels: entry.labels,
+                transitions,
+                t,
+                attributes: entry.attributes.into_iter().collect(),
+                call,
+                origins: Vec::new(),
+                label: entry.label,
+                id: entry.id,
+                loc
+            });
+        }
+
+        symbols.tests = document.tests.into_iter().enumerate()
+            .map(|(i, x)| Test { loc: at(i + 1, 0), input: x.input, expected: x.expected })
+            .collect();
+
+        symbols
+    }
+
+    fn to_document(&self) -> Document {
+
+        let mut ids: Vec<&String> = self.objects.keys().collect();
+        ids.sort();
+
+        let objects = ids.into_iter().map(|id| {
+            let obj = &self.objects[id];
+            let dir = Path::new(&obj.loc.file).parent().unwrap_or(Path::new(""));
+
+            Entry {
+                id: obj.id.clone(),
+                entry: (obj.t & StateTypes::EntryPoint) != StateTypes::Through,
+                end: (obj.t & StateTypes::EndPoint) != StateTypes::Through,
+                label: Some(obj.label().to_string()).filter(|x| *x != obj.id),
+                litterals: obj.values.iter().map(|&i| self.values[i].0.clone()).collect(),
+                labels: obj.labels.clone(),
+                attributes: obj.attributes.iter().cloned().collect(),
+                call: obj.call.as_ref().map(|key| Path::new(key).strip_prefix(dir).map_or(key.clone(), |x| x.to_string_lossy().to_string())),
+                transitions: obj.transitions.iter().enumerate().map(|(i, tr)| Step {
+                    on: tr.origin.clone(),
+                    conditions: tr.conditions.iter().map(|x| Lookahead { negated: x.negated, sets: x.sets.clone() }).collect(),
+                    target: tr.target.clone(),
+                    writing: tr.behaviour,
+                    stack: tr.stack,
+                    rewrite: tr.rewrite.clone(),
+                    attributes: tr.attributes.iter().cloned().collect(),
+                    alternative: i > 0 && obj.transitions[i - 1].loc == tr.loc && obj.transitions[i - 1].origin == tr.origin
+                        && obj.transitions[i - 1].conditions == tr.conditions && obj.transitions[i - 1].rewrite == tr.rewrite,
+                    keep_node: tr.keep_node
+                }).collect()
+            }
+        }).collect();
+
+        let tests = self.tests.iter().map(|x| Example { input: x.input.clone(), expected: x.expected.clone() }).collect();
+
+        Document { version: SCHEMA_VERSION, objects, tests }
+    }
+
+    pub(crate) fn json_from(input: &str, name: &str, verbose: bool, callers: &mut Vec<PathBuf>) -> Self {
+        match serde_json::from_str(input) {
+            Ok(document) => Self::from_document(document, name, verbose, callers),
+            Err(e) => Self::malformed(Pos { file: name.to_string(), line: e.line(), column: e.column() }, e.to_string())
+        }
+    }
+
+    #[cfg(feature = "yaml")]
+    pub(crate) fn yaml_from(input: &str, name: &str, verbose: bool, callers: &mut Vec<PathBuf>) -> Self {
+        match serde_yaml::from_str(input) {
+            Ok(document) => Self::from_document(document, name, verbose, callers),
+            Err(e) => {
+                let (line, column) = e.location().map_or((0, 0), |x| (x.line(), x.column()));
+                Self::malformed(Pos { file: name.to_string(), line, column }, e.to_string())
+            }
+        }
+    }
+
+    fn malformed(loc: Pos, message: String) -> Self {
+        let mut symbols = Self::empty();
+        symbols.errors.push(Box::new(ParserError::Malformed(loc, message)));
+        symbols
+    }
+
+    // Machine read from a JSON document, errors being kept as for a rule file
+    pub fn from_json(input: &str, name: &str) -> Self {
+        Self::json_from(input, name, false, &mut Vec::new())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.to_document()).unwrap()
+    }
+
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml(input: &str, name: &str) -> Self {
+        Self::yaml_from(input, name, false, &mut Vec::new())
+    }
+
+    #[cfg(feature = "yaml")]
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(&self.to_document()).unwrap()
+    }
+}
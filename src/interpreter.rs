@@ -0,0 +1,569 @@
+use std::{collections::{BTreeMap, HashMap}, error::Error, fmt::Display};
+
+use serde::{Serialize, Deserialize};
+
+use super::parser::*;
+
+#[derive(Debug)]
+pub enum InterpreterError{
+    NoEntryPoint,
+    SeveralEntryPoints(String, String),
+    UndefinedIdentifier(String),
+    NoTransition(usize, String, String),
+    NotAnEndPoint(usize, String),
+    MaxDepth(usize, String),
+    UnbalancedPop(usize, String),
+    Ambiguous(usize, usize),
+    NoRunFinished(usize),
+    Unreadable(usize, String)
+}
+
+impl Error for InterpreterError {}
+
+impl Display for InterpreterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+
+        match self {
+            InterpreterError::NoEntryPoint => writeln!(f, "No entry point defined"),
+            InterpreterError::SeveralEntryPoints(first, second) => writeln!(f, "Several entry points defined: '{}' and '{}'", first, second),
+            InterpreterError::UndefinedIdentifier(id) => writeln!(f, "Undefined identifier: '{}'", id),
+            InterpreterError::NoTransition(at, state, unit) => writeln!(f, "input:{:0>3} --> No transition from state '{}' for {:?}", at, state, unit),
+            InterpreterError::NotAnEndPoint(at, state) => writeln!(f, "input:{:0>3} --> Input ended in state '{}', which is not an end point", at, state),
+            InterpreterError::MaxDepth(at, state) => writeln!(f, "input:{:0>3} --> Maximum depth reached when pushing state '{}'", at, state),
+            InterpreterError::UnbalancedPop(at, state) => writeln!(f, "input:{:0>3} --> Pop from state '{}' without a matching push", at, state),
+            InterpreterError::Ambiguous(start, end) => writeln!(f, "input:{:0>3}-{:0>3} --> Ambiguous tokenization", start, end),
+            InterpreterError::NoRunFinished(at) => writeln!(f, "input:{:0>3} --> No run of the machine reached the end of the input", at),
+            InterpreterError::Unreadable(at, e) => writeln!(f, "input:{:0>3} --> Cannot read the input: {}", at, e),
+        }
+
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Node {
+    pub id: String,
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+    pub attributes: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Node>
+}
+
+impl Node {
+
+    pub(crate) fn new(state: &Object, at: usize) -> Self {
+        Self {
+            id: state.label().to_string(),
+            text: String::new(),
+            start: at,
+            end: at,
+            attributes: state.attributes.iter().cloned().collect(),
+            children: Vec::new()
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.text.is_empty() && self.children.is_empty()
+    }
+
+    // The written text may differ from the input when rewritten, the span always refers to the original text
+    // Nodes read from a previous machine are separated by a space when they were not contiguous
+    pub(crate) fn push(&mut self, text: &str, at: usize, end: usize, read: &[Node]) {
+        if self.is_empty() {
+            self.start = at;
+        }
+        else if !read.is_empty() && !self.text.is_empty() && at > self.end {
+            self.text.push(' ');
+        }
+        self.text.push_str(text);
+        self.children.extend(read.iter().cloned());
+        self.end = end;
+    }
+}
+
+// What a machine reads: raw text, or the nodes written by a previous machine
+// Positions are byte offsets in the first case, and indices in the second one
+pub trait Units {
+    fn size(&self) -> usize;
+    // Length of the single unit read by '@'
+    fn unit(&self, pos: usize) -> Option<usize>;
+    fn litteral(&self, pos: usize, litteral: &str) -> Option<usize>;
+    fn label(&self, pos: usize, label: &str) -> Option<usize>;
+    fn text(&self, pos: usize, len: usize) -> &str;
+    // First byte of the text of the unit, which litterals found there start with
+    fn lead(&self, pos: usize) -> Option<u8>;
+    // Character a single character litteral found there would be, with the length it would match
+    fn single(&self, pos: usize) -> Option<(char, usize)>;
+    // Text a litteral of this many bytes found there would be
+    fn slice(&self, pos: usize, len: usize) -> Option<&str>;
+    // Whole input, when units are the characters of a text
+    fn source(&self) -> Option<&str>;
+    // Nodes read, which become the children of the node they are written to
+    fn nodes(&self, pos: usize, len: usize) -> &[Node];
+    // Offsets in the original text
+    fn start(&self, pos: usize) -> usize;
+    fn end(&self, pos: usize) -> usize;
+}
+
+impl Units for str {
+
+    fn size(&self) -> usize {
+        self.len()
+    }
+
+    fn unit(&self, pos: usize) -> Option<usize> {
+        self[pos..].chars().next().map(|c| c.len_utf8())
+    }
+
+    fn litteral(&self, pos: usize, litteral: &str) -> Option<usize> {
+        if !litteral.is_empty() && self[pos..].starts_with(litteral) { Some(litteral.len()) } else { None }
+    }
+
+    fn label(&self, _: usize, _: &str) -> Option<usize> {
+        None
+    }
+
+    fn text(&self, pos: usize, len: usize) -> &str {
+        &self[pos..pos + len]
+    }
+
+    fn lead(&self, pos: usize) -> Option<u8> {
+        self.as_bytes().get(pos).copied()
+    }
+
+    fn single(&self, pos: usize) -> Option<(char, usize)> {
+        self[pos..].chars().next().map(|c| (c, c.len_utf8()))
+    }
+
+    fn slice(&self, pos: usize, len: usize) -> Option<&str> {
+        self.get(pos..pos + len)
+    }
+
+    fn source(&self) -> Option<&str> {
+        Some(self)
+    }
+
+    fn nodes(&self, _: usize, _: usize) -> &[Node] {
+        &[]
+    }
+
+    fn start(&self, pos: usize) -> usize {
+        pos
+    }
+
+    fn end(&self, pos: usize) -> usize {
+        pos
+    }
+}
+
+impl Units for [Node] {
+
+    fn size(&self) -> usize {
+        self.len()
+    }
+
+    fn unit(&self, pos: usize) -> Option<usize> {
+        self.get(pos).map(|_| 1)
+    }
+
+    fn litteral(&self, pos: usize, litteral: &str) -> Option<usize> {
+        self.get(pos).filter(|x| x.text == litteral).map(|_| 1)
+    }
+
+    fn label(&self, pos: usize, label: &str) -> Option<usize> {
+        self.get(pos).filter(|x| x.id == label).map(|_| 1)
+    }
+
+    fn text(&self, pos: usize, len: usize) -> &str {
+        if len == 0 { "" } else { &self[pos].text }
+    }
+
+    fn lead(&self, pos: usize) -> Option<u8> {
+        self.get(pos).and_then(|x| x.text.as_bytes().first().copied())
+    }
+
+    fn single(&self, pos: usize) -> Option<(char, usize)> {
+        let mut chars = self.get(pos)?.text.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some((c, 1)),
+            _ => None
+        }
+    }
+
+    fn slice(&self, pos: usize, len: usize) -> Option<&str> {
+        self.get(pos).map(|x| x.text.as_str()).filter(|x| x.len() == len)
+    }
+
+    fn source(&self) -> Option<&str> {
+        None
+    }
+
+    fn nodes(&self, pos: usize, len: usize) -> &[Node] {
+        &self[pos..pos + len]
+    }
+
+    fn start(&self, pos: usize) -> usize {
+        self.get(pos).or(self.last()).map_or(0, |x| if pos < self.len() { x.start } else { x.end })
+    }
+
+    fn end(&self, pos: usize) -> usize {
+        if pos == 0 { self.start(0) } else { self[pos - 1].end }
+    }
+}
+
+// Notified of what a machine does while it runs, for coverage or profiling
+pub trait Observer {
+    // The state becomes the current one
+    fn enter(&mut self, _state: &Object) {}
+    // The transition of the state fires on the text read
+    fn fire(&mut self, _state: &Object, _transition: &Transition, _text: &str) {}
+    // The state delegates to its machine, until it returns
+    fn call(&mut self, _state: &Object) {}
+    fn back(&mut self, _state: &Object) {}
+    // The run is over, whether it succeeded or not
+    fn done(&mut self) {}
+}
+
+impl Observer for () {}
+
+// Pseudo-sets that are not looked up in the symbol table
+pub(crate) const PSEUDO_SETS: [&str; 3] = ["@", "$", "^"];
+
+const MAX_DEPTH: usize = 64;
+
+// A level opened by a push, waiting for its pop
+#[derive(Clone)]
+pub(crate) struct Frame<'a> {
+    pub(crate) origin: &'a Object,
+    pub(crate) container: Node,
+    pub(crate) parent: Vec<Node>
+}
+
+// Mutable state of a single run over an input
+#[derive(Clone)]
+pub(crate) struct Run<'a> {
+    pub(crate) current: &'a Object,
+    pub(crate) node: Node,
+    pub(crate) nodes: Vec<Node>,
+    pub(crate) frames: Vec<Frame<'a>>
+}
+
+impl<'a> Run<'a> {
+
+    pub(crate) fn flush(&mut self) {
+        if !self.node.is_empty() {
+            self.nodes.push(self.node.clone());
+        }
+    }
+
+    pub(crate) fn close(&mut self, at: usize) -> Option<&'a Object> {
+        let mut frame = self.frames.pop()?;
+
+        frame.container.end = at;
+        frame.container.children = std::mem::replace(&mut self.nodes, frame.parent);
+        if !frame.container.children.is_empty() {
+            self.nodes.push(frame.container);
+        }
+
+        Some(frame.origin)
+    }
+}
+
+pub struct Interpreter<'a> {
+    pub(crate) symbols: &'a Symbols,
+    pub(crate) entry: &'a Object,
+    pub(crate) max_depth: usize,
+    pub(crate) machines: HashMap<&'a str, Interpreter<'a>>
+}
+
+impl<'a> Interpreter<'a> {
+
+    pub fn new(symbols: &'a Symbols) -> Result<Self, InterpreterError> {
+
+        let mut entry: Option<&Object> = None;
+
+        for obj in symbols.objects.values() {
+            if (obj.t & StateTypes::EntryPoint) != StateTypes::Through {
+                if let Some(other) = entry {
+                    return Err(InterpreterError::SeveralEntryPoints(other.id.clone(), obj.id.clone()));
+                }
+                entry = Some(obj);
+            }
+        }
+
+        match entry {
+            Some(obj) => Self::with_entry(symbols, &obj.id),
+            None => Err(InterpreterError::NoEntryPoint)
+        }
+    }
+
+    pub fn with_entry(symbols: &'a Symbols, id: &str) -> Result<Self, InterpreterError> {
+
+        for obj in symbols.objects.values() {
+            for tr in obj.transitions.iter() {
+                let sets = tr.conditions.iter().flat_map(|x| x.sets.iter());
+
+                for set in std::iter::once(&tr.origin).chain(sets) {
+                    if !PSEUDO_SETS.contains(&set.as_str()) && !symbols.objects.contains_key(set) {
+                        return Err(InterpreterError::UndefinedIdentifier(set.clone()));
+                    }
+                }
+                if tr.target != "." && !symbols.objects.contains_key(&tr.target) {
+                    return Err(InterpreterError::UndefinedIdentifier(tr.target.clone()));
+                }
+            }
+        }
+
+        // Called machines are checked in turn; the parser already refused recursive calls
+        let mut machines = HashMap::new();
+        for (key, machine) in symbols.machines.iter() {
+            machines.insert(key.as_str(), Interpreter::new(machine)?);
+        }
+
+        match symbols.objects.get(id) {
+            Some(entry) => Ok(Self { symbols, entry, max_depth: MAX_DEPTH, machines }),
+            None => Err(InterpreterError::UndefinedIdentifier(id.to_string()))
+        }
+    }
+
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self.machines = self.machines.into_iter().map(|(key, machine)| (key, machine.max_depth(depth))).collect();
+        self
+    }
+
+    pub fn run<U: Units + ?Sized>(&self, input: &U) -> Result<Vec<Node>, InterpreterError> {
+        self.execute(input, 0, false, &mut ()).map(|(nodes, _, _)| nodes)
+    }
+
+    // Length of the longest litteral of the set found at the position, going through the values of the set one by one
+    pub fn lookup(&self, set: &str, input: &str, pos: usize) -> Option<usize> {
+        self.match_set(set, input, pos)
+    }
+
+    // Same as run, telling the observer about every state entered and every transition fired
+    pub fn run_with<U: Units + ?Sized>(&self, input: &U, observer: &mut dyn Observer) -> Result<Vec<Node>, InterpreterError> {
+        let result = self.execute(input, 0, false, observer).map(|(nodes, _, _)| nodes);
+        observer.done();
+        result
+    }
+
+    // When called from another machine, the run stops at the first token it has no transition for
+    // Returns the nodes, where the machine stopped reading and the state it ended in
+    pub(crate) fn execute<U: Units + ?Sized>(&self, input: &U, from: usize, called: bool, observer: &mut dyn Observer) -> Result<(Vec<Node>, usize, &'a Object), InterpreterError> {
+
+        let mut run = Run {
+            current: self.entry,
+            node: Node::new(self.entry, input.start(from)),
+            nodes: Vec::new(),
+            frames: Vec::new()
+        };
+
+        observer.enter(run.current);
+
+        if let Some(tr) = run.current.transitions.iter().find(|x| x.origin == "^") {
+            self.step(tr, &mut run, input, from, 0, observer)?;
+        }
+
+        let mut pos = from;
+        while pos < input.size() {
+
+            match self.select(run.current, input, pos) {
+                Some((tr, len)) => {
+                    pos = self.step(tr, &mut run, input, pos, len, observer)?;
+                },
+                None if called => break,
+                None => {
+                    let unit = input.text(pos, input.unit(pos).unwrap_or(0)).to_string();
+                    return Err(InterpreterError::NoTransition(input.start(pos), run.current.id.clone(), unit));
+                }
+            }
+        }
+
+        if pos == input.size() {
+            if let Some(tr) = run.current.transitions.iter().find(|x| x.origin == "$") {
+                self.step(tr, &mut run, input, pos, 0, observer)?;
+            }
+        }
+
+        // Levels still open at the end of the input are closed implicitly
+        run.flush();
+        while run.close(input.end(pos)).is_some() {}
+
+        if (run.current.t & StateTypes::EndPoint) == StateTypes::Through {
+            return Err(InterpreterError::NotAnEndPoint(input.end(pos), run.current.id.clone()));
+        }
+
+        Ok((run.nodes, pos, run.current))
+    }
+
+    fn step<U: Units + ?Sized>(&self, tr: &Transition, run: &mut Run<'a>, input: &U, at: usize, len: usize, observer: &mut dyn Observer) -> Result<usize, InterpreterError> {
+
+        observer.fire(run.current, tr, input.text(at, len));
+        let next = self.apply(tr, run, input, at, len, observer)?;
+
+        // Popping back to '.' returns to the state that pushed
+        if tr.target != "." || tr.stack == StackBehaviour::Pop {
+            observer.enter(run.current);
+        }
+
+        Ok(next)
+    }
+
+    // Picks the transition matching the longest litteral at the current position, or '@' for a single character
+    // Ties are resolved by declaration order; a shorter litteral of the set is tried when the conditions fail on the longest one
+    pub(crate) fn select<U: Units + ?Sized>(&self, current: &'a Object, input: &U, pos: usize) -> Option<(&'a Transition, usize)> {
+
+        let mut best: Option<(&Transition, usize)> = None;
+
+        for tr in current.transitions.iter().filter(|x| !PSEUDO_SETS.contains(&x.origin.as_str())) {
+            let len = self.match_lengths(&tr.origin, input, pos).into_iter().find(|&len| self.check(&tr.conditions, input, pos + len));
+            if let Some(len) = len.filter(|&len| best.is_none_or(|(_, l)| len > l)) {
+                best = Some((tr, len));
+            }
+        }
+
+        // '@' only stands for tokens no transition on a set of the state accepts
+        best.or_else(|| {
+            let len = input.unit(pos)?;
+            current.transitions.iter()
+                .filter(|x| x.origin == "@")
+                .find(|x| self.check(&x.conditions, input, pos + len))
+                .map(|tr| (tr, len))
+        })
+    }
+
+    // Length of the longest litteral or label of the set found at the position
+    pub(crate) fn match_set<U: Units + ?Sized>(&self, set: &str, input: &U, pos: usize) -> Option<usize> {
+        match set {
+            "@" => input.unit(pos),
+            "$" => if pos == input.size() { Some(0) } else { None },
+            _ => {
+                let obj = self.symbols.objects.get(set)?;
+                let litterals = obj.values.iter().filter_map(|&i| input.litteral(pos, &self.symbols.values[i].0));
+                let labels = obj.labels.iter().filter_map(|x| input.label(pos, x));
+                litterals.chain(labels).max()
+            }
+        }
+    }
+
+    // Lengths of all the litterals and labels of the set found at the position, longest first
+    pub(crate) fn match_lengths<U: Units + ?Sized>(&self, set: &str, input: &U, pos: usize) -> Vec<usize> {
+        let mut out: Vec<usize> = match self.symbols.objects.get(set) {
+            Some(obj) => {
+                let litterals = obj.values.iter().filter_map(|&i| input.litteral(pos, &self.symbols.values[i].0));
+                litterals.chain(obj.labels.iter().filter_map(|x| input.label(pos, x))).collect()
+            },
+            None => self.match_set(set, input, pos).into_iter().collect()
+        };
+        out.sort_by(|a, b| b.cmp(a));
+        out.dedup();
+        out
+    }
+
+    // Lookahead conditions only peek at the input, nothing is consumed
+    pub(crate) fn check<U: Units + ?Sized>(&self, conditions: &[Condition], input: &U, pos: usize) -> bool {
+        conditions.iter().all(|cond| {
+            let mut at = pos;
+            let matched = cond.sets.iter().all(|set| {
+                match self.match_set(set, input, at) {
+                    Some(len) => { at += len; true },
+                    None => false
+                }
+            });
+            matched != cond.negated
+        })
+    }
+
+    // Returns the position of the next token to read
+    pub(crate) fn apply<U: Units + ?Sized>(&self, tr: &Transition, run: &mut Run<'a>, input: &U, at: usize, len: usize, observer: &mut dyn Observer) -> Result<usize, InterpreterError> {
+
+        let text = tr.rewrite.as_deref().unwrap_or(input.text(at, len));
+        let read = if tr.rewrite.is_some() { &[] } else { input.nodes(at, len) };
+        let end = at + len;
+        let (offset, end_offset) = (input.start(at), input.end(end));
+
+        // Attributes of the transition go to the node receiving the token, or to the one it closes
+        if (tr.target == "." || tr.keep_node) && tr.stack == StackBehaviour::Keep {
+            let next = if tr.target == "." { run.current } else { &self.symbols.objects[&tr.target] };
+
+            match tr.behaviour {
+                WritingBehaviour::WriteAfter => {
+                    run.node.push(text, offset, end_offset, read);
+                    run.node.attributes.extend(tr.attributes.iter().cloned());
+                    run.current = next;
+                    return Ok(end);
+                },
+                WritingBehaviour::NoWrite => {
+                    run.node.attributes.extend(tr.attributes.iter().cloned());
+                    run.current = next;
+                    return Ok(end);
+                },
+                WritingBehaviour::WriteBefore => {}
+            }
+        }
+
+        if tr.behaviour != WritingBehaviour::WriteAfter {
+            if tr.behaviour == WritingBehaviour::WriteBefore {
+                run.node.push(text, offset, end_offset, read);
+            }
+            run.node.attributes.extend(tr.attributes.iter().cloned());
+        }
+        run.flush();
+
+        let start = if tr.behaviour == WritingBehaviour::WriteAfter { at } else { end };
+        let start_offset = if tr.behaviour == WritingBehaviour::WriteAfter { offset } else { input.start(end) };
+        let target = if tr.target == "." { None } else { Some(&self.symbols.objects[&tr.target]) };
+
+        run.current = match tr.stack {
+            StackBehaviour::Keep => target.unwrap_or(run.current),
+            StackBehaviour::Push => {
+                let next = target.unwrap_or(run.current);
+
+                if run.frames.len() >= self.max_depth {
+                    return Err(InterpreterError::MaxDepth(offset, next.id.clone()));
+                }
+
+                run.frames.push(Frame {
+                    origin: run.current,
+                    container: Node::new(next, start_offset),
+                    parent: std::mem::take(&mut run.nodes)
+                });
+                next
+            },
+            StackBehaviour::Pop => {
+                match run.close(input.end(start)) {
+                    Some(origin) => target.unwrap_or(origin),
+                    None => return Err(InterpreterError::UnbalancedPop(offset, run.current.id.clone()))
+                }
+            }
+        };
+
+        run.node = Node::new(run.current, start_offset);
+
+        // The called machine reads from the token if it was to be written in the new state, and its nodes are nested in ours
+        if let Some(machine) = run.current.call.as_deref().and_then(|x| self.machines.get(x)) {
+            observer.call(run.current);
+            let (children, stop, _) = machine.execute(input, start, true, observer)?;
+            observer.back(run.current);
+
+            if stop > start {
+                if tr.behaviour == WritingBehaviour::WriteAfter {
+                    run.node.attributes.extend(tr.attributes.iter().cloned());
+                }
+                run.node.children = children;
+                run.node.end = input.end(stop);
+                run.nodes.push(std::mem::replace(&mut run.node, Node::new(run.current, input.start(stop))));
+                return Ok(stop);
+            }
+        }
+
+        if tr.behaviour == WritingBehaviour::WriteAfter {
+            run.node.push(text, offset, end_offset, read);
+            run.node.attributes.extend(tr.attributes.iter().cloned());
+        }
+
+        Ok(end)
+    }
+}
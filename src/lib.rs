@@ -1,35 +1,1302 @@
-mod scanner;
-mod parser;
-
-use parser::*;
-use std::fs;
+pub mod scanner;
+pub mod parser;
+pub mod interpreter;
+pub mod export;
+pub mod pipeline;
+pub mod nfa;
+pub mod determinize;
+pub mod minimize;
+pub mod equivalence;
+pub mod diff;
+pub mod enumerate;
+pub mod testing;
+pub mod coverage;
+pub mod profile;
+pub mod compile;
+pub mod borrowed;
+pub mod parallel;
+pub mod cursor;
+pub mod stmc;
+pub mod interchange;
 
 #[cfg(test)]
 mod tests {
     use crate::parser::Symbols;
+    use crate::interpreter::Interpreter;
 
     use super::scanner::*;
     use std::fs;
 
+    // Folder of its own for the test and the run, which the test removes once done
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("stamesp_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
     #[test]
     fn it_works() {
-        
-        let input = fs::read_to_string("test.txt").expect("Cannot read the file");
 
-        let scan = Scanner::new(input.as_str(), "test.txt");
+        let sym = Symbols::from_file("test.txt", false).expect("Cannot read the file");
+        assert!(sym.errors().is_empty());
+
+        let report = sym.run_tests().unwrap();
+        assert_eq!(report.passed, 2);
+        assert!(report.failures.is_empty(), "{}", report);
+
+        // Tests are kept apart from the machine
+        assert!(!sym.objects.contains_key("test"));
+
+        let rules = "
+            sep(' ');
+            Fo*:{ sep = ^Fo; @ = .; };
+            test \"le chat\" => [\"le\", \"chien\"];
+            test \"le  chat\" => [\"le\"];
+        ";
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        let report = sym.run_tests().unwrap();
+        assert_eq!(report.failures.len(), 2);
+        assert_eq!(report.failures[0].loc.line, 4);
+        assert_eq!(report.failures[0].to_string(), "rules:004:013 --> Test failed on \"le chat\"\n      \"le\"\n    - \"chien\"\n    + \"chat\"\n");
+
+        let sym = Symbols::parser(Scanner::new("test \"a\" \"b\";", "rules"), false);
+        assert!(!sym.errors.is_empty());
+
+        // '=>' is a single token, and commas only separate the expected texts
+        for rules in ["test \"a\" > [\"a\"];", "test \"a\" = > [\"a\"];", "test \"a\" => => [\"a\"];", "sep(' ', 'x'); Fo*:{ sep = ^.; };"] {
+            let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+            assert!(!sym.errors.is_empty(), "{}", rules);
+        }
+        let sym = Symbols::parser(Scanner::new("sep(' '); Fo*:{ sep = ^Fo; @ = .; }; test \"a b\" => [\"a\", \"b\"];", "rules"), false);
+        assert!(sym.errors.is_empty());
+        assert_eq!(sym.run_tests().unwrap().passed, 1);
+
+        // Outside of tests, '=>' pushes the target as '= >' does
+        let sym = Symbols::parser(Scanner::new("dot('.'); Doc*:{ @ =>Fo; }; Fo:{ dot = %<.; @ = .; };", "rules"), false);
+        assert!(sym.errors.is_empty());
+        assert_eq!(Interpreter::new(&sym).unwrap().run("ab.").unwrap()[0].children[0].text, "ab.");
+    }
+
+    #[test]
+    fn end_of_input() {
+
+        let rules = "
+            sep(' ');
+            Word:{ sep = ^Sep; @ = .; $ = %End; };
+            Sep*{ ^ = ^Word; sep = ^.; @ = Word; };
+            End:{};
+        ";
+
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        let interpreter = Interpreter::new(&sym).unwrap();
+        let nodes = interpreter.run("le chat").unwrap();
+
+        let texts: Vec<_> = nodes.iter().map(|x| (x.id.as_str(), x.text.as_str())).collect();
+        assert_eq!(texts, vec![("Word", "le"), ("Word", "chat")]);
+
+        assert!(interpreter.run("le chat ").is_err());
+
+        // '^' is taken once, before the first character: the first word is a Title unless a separator comes first
+        let rules = "
+            sep(' ');
+            Word:{ sep = ^Sep; @ = .; $ = %End; };
+            Title:{ sep = ^Sep; @ = .; $ = %End; };
+            Sep*{ ^ = ^Title; sep = ^.; @ = Word; };
+            End:{};
+        ";
+
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        let interpreter = Interpreter::new(&sym).unwrap();
+        let compiled = interpreter.compile();
+
+        for (input, expected) in [("le chat", vec![("Title", "le"), ("Word", "chat")]), (" le chat", vec![("Word", "le"), ("Word", "chat")])] {
+            let nodes = interpreter.run(input).unwrap();
+            let texts: Vec<_> = nodes.iter().map(|x| (x.id.as_str(), x.text.as_str())).collect();
+            assert_eq!(texts, expected);
+            assert_eq!(compiled.run(input).unwrap(), nodes);
+        }
+    }
+
+    #[test]
+    fn lookahead() {
+
+        let rules = "
+            sep(' ');
+            upper('L' 'C');
+            dot('.');
+            Fo:{ sep = ^Sep; dot &(sep upper) = End; dot !($) = .; @ = .; };
+            End:{ sep = ^Sep; @ = Fo; };
+            Sep*{ sep = ^.; @ = Fo; };
+        ";
+
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        let interpreter = Interpreter::new(&sym).unwrap();
+        let nodes = interpreter.run("M.Dupont vient. Le chat").unwrap();
+
+        let texts: Vec<_> = nodes.iter().map(|x| x.text.as_str()).collect();
+        assert_eq!(texts, vec!["M.Dupont", "vient", ".", "Le", "chat"]);
+
+        assert_eq!(interpreter.run("fin.").unwrap()[0].text, "fin.");
+        assert!(sym.to_dot().contains("label=\"dot &(sep upper)\""));
+
+        // '@' is taken when the conditions fail, and a shorter litteral of the set when they fail on the longest one
+        let rules = "
+            sep(' ');
+            dot('.');
+            upper('L');
+            Fo*:{ sep = ^Fo; dot &(sep upper) = %Fo; @ = .; };
+        ";
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        let interpreter = Interpreter::new(&sym).unwrap();
+        let nodes = interpreter.run("M.Dupont").unwrap();
+        assert_eq!(nodes.iter().map(|x| x.text.as_str()).collect::<Vec<_>>(), vec!["M.Dupont"]);
+        assert_eq!(interpreter.compile().run("M.Dupont").unwrap(), nodes);
+
+        let rules = "
+            x('a' 'ab');
+            b('b');
+            Fo*:{ x &(b) = %Fo; b = .; @ = .; };
+        ";
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        let interpreter = Interpreter::new(&sym).unwrap();
+        let nodes = interpreter.run("ab").unwrap();
+        assert_eq!(nodes.iter().map(|x| x.text.as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+        assert_eq!(interpreter.compile().run("ab").unwrap(), nodes);
+
+        let sym = Symbols::parser(Scanner::new("dot('.'); Fo*:{ dot !($) = .; };", "rules"), false);
+        let interpreter = Interpreter::new(&sym).unwrap();
+        assert!(interpreter.run(".").is_err());
+        assert!(interpreter.run("..").is_err());
+
+        // The start of the input is never ahead, in rules as in documents
+        let errors = |sym: &Symbols| sym.errors().iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        let sym = Symbols::parser(Scanner::new("dot('.'); Fo*:{ dot &(^) = .; dot !(dot ^) = .; };", "rules"), false);
+        assert_eq!(errors(&sym), vec![
+            "rules:001:023 --> Lookahead on '^', while the start of the input is never ahead\n",
+            "rules:001:041 --> Lookahead on '^', while the start of the input is never ahead\n"
+        ]);
+        let document = r#"{ "version": 1, "objects": [{ "id": "Fo", "entry": true, "end": true, "transitions": [{ "on": "@", "conditions": [{ "sets": ["^"] }], "target": "." }] }] }"#;
+        assert_eq!(errors(&Symbols::from_json(document, "doc.json")), vec!["doc.json:001:000 --> Lookahead on '^', while the start of the input is never ahead\n"]);
+    }
+
+    #[test]
+    fn rewriting() {
+
+        let rules = "
+            sep(' ');
+            apos('’');
+            shy('\u{ad}');
+            Fo*:{ sep = ^Fo; apos -> \"'\" = %Fo; shy -> \"\" = .; @ = .; };
+        ";
+
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        let interpreter = Interpreter::new(&sym).unwrap();
+
+        let input = "l’hom\u{ad}me";
+        let nodes = interpreter.run(input).unwrap();
+
+        let texts: Vec<_> = nodes.iter().map(|x| x.text.as_str()).collect();
+        assert_eq!(texts, vec!["l'", "homme"]);
+        assert_eq!(&input[nodes[0].start..nodes[0].end], "l’");
+    }
+
+    #[test]
+    fn attributes() {
+
+        let rules = "
+            sep(' ');
+            PoncForte:('.'){ sep = ^Sep; PoncForte = PoncForte [type=\"sent_end\"]; @ = Fo; };
+            Fo:[pos=\"WORD\"]{ sep = ^Sep; PoncForte = PoncForte [type=\"sent_end\"]; @ = .; };
+            Sep*{ sep = ^.; @ = Fo; };
+        ";
+
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        let nodes = Interpreter::new(&sym).unwrap().run("Fin..").unwrap();
+
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(nodes[0].attributes.get("pos").map(String::as_str), Some("WORD"));
+        assert_eq!(nodes[2].attributes.get("type").map(String::as_str), Some("sent_end"));
+
+        let xml = crate::export::to_xml(&nodes);
+        assert!(xml.contains("<node id=\"Fo\" start=\"0\" end=\"3\" pos=\"WORD\">Fin</node>"));
+        assert!(crate::export::to_json(&nodes).contains("\"type\": \"sent_end\""));
+
+        // Names that are not XML names, or those every node already has, are refused in rules and documents
+        let errors = |sym: &Symbols| sym.errors().iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        let sym = Symbols::parser(Scanner::new("Fo*:[start=\"x\" 2a=\"y\" pos=\"z\"]{ @ = .; };", "rules"), false);
+        assert_eq!(errors(&sym), vec![
+            "rules:001:006 --> Invalid attribute name: 'start'\n",
+            "rules:001:016 --> Invalid attribute name: '2a'\n"
+        ]);
+        let document = r#"{ "version": 1, "objects": [{ "id": "Fo", "entry": true, "end": true, "attributes": { "a b": "x", "id": "y" } }] }"#;
+        assert_eq!(errors(&Symbols::from_json(document, "doc.json")), vec![
+            "doc.json:001:000 --> Invalid attribute name: 'a b'\n",
+            "doc.json:001:000 --> Invalid attribute name: 'id'\n"
+        ]);
+    }
+
+    #[test]
+    fn nesting() {
+
+        let rules = "
+            sep(' ');
+            dot('.');
+            Doc*:{ sep = ^.; @ = >Sentence; };
+            Sentence:{ sep = ^Sep; dot = %<.; @ = .; };
+            Sep:{ sep = ^.; dot = %<.; @ = Sentence; };
+        ";
+
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        let interpreter = Interpreter::new(&sym).unwrap();
+        let nodes = interpreter.run("Le chat dort. Il rêve").unwrap();
+
+        let texts = |node: &crate::interpreter::Node| node.children.iter().map(|x| x.text.clone()).collect::<Vec<_>>();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(texts(&nodes[0]), vec!["Le", "chat", "dort."]);
+        assert_eq!(texts(&nodes[1]), vec!["Il", "rêve"]);
+
+        assert!(Interpreter::new(&sym).unwrap().max_depth(0).run("Fin").is_err());
+
+        let sym = Symbols::parser(Scanner::new("dot('.'); Doc*:{ dot = %<.; };", "rules"), false);
+        assert!(Interpreter::new(&sym).unwrap().run(".").is_err());
+    }
+
+    #[test]
+    fn call() {
+
+        let dir = temp_dir("call");
+
+        fs::write(dir.join("numbers.stm"), "
+            digit('0' '1' '2' '3' '4' '5' '6' '7' '8' '9');
+            comma(',');
+            Int*:{ digit = .; comma &(digit) = %Frac; };
+            Frac:{ digit = .; };
+        ").unwrap();
+        fs::write(dir.join("loop.stm"), "Loop*:call \"loop.stm\"{};").unwrap();
+
+        let rules = "
+            sep(' ');
+            digit('0' '1' '2' '3' '4' '5' '6' '7' '8' '9');
+            Fo:{ sep = ^Sep; @ = .; };
+            Number:call \"numbers.stm\"{ sep = ^Sep; @ = Fo; };
+            Sep*{ sep = ^.; digit = Number; @ = Fo; };
+        ";
+
+        let file = dir.join("main.stm").to_string_lossy().to_string();
+        let sym = Symbols::parser(Scanner::new(rules, &file), false);
+        let nodes = Interpreter::new(&sym).unwrap().run("pi 3,14 ok").unwrap();
+
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(nodes[1].id, "Number");
+        let texts: Vec<_> = nodes[1].children.iter().map(|x| (x.id.as_str(), x.text.as_str())).collect();
+        assert_eq!(texts, vec![("Int", "3,"), ("Frac", "14")]);
+
+        let file = dir.join("loop.stm").to_string_lossy().to_string();
+        let sym = Symbols::parser(Scanner::new("Loop*:call \"loop.stm\"{};", &file), false);
+        assert!(!sym.errors.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pipeline() {
+
+        let tokens = "
+            sep(' ');
+            PoncForte:('.'){ sep = ^Sep; PoncForte = .; @ = Fo; };
+            Fo:{ sep = ^Sep; PoncForte = PoncForte; @ = .; };
+            Sep*:{ sep = ^.; PoncForte = PoncForte; @ = Fo; };
+        ";
+        let sentences = "
+            end(PoncForte);
+            Sentence*:{ end = %Sentence; @ = .; };
+        ";
+
+        let tokens = Symbols::parser(Scanner::new(tokens, "tokens"), false);
+        let sentences = Symbols::parser(Scanner::new(sentences, "sentences"), false);
 
-        for tok in scan.clone() {
-            match tok {
-                Err(e) => println!("{}", e),
-                Ok(val) => println!("{}", val)
+        let pipeline = crate::pipeline::Pipeline::new()
+            .stage(Interpreter::new(&tokens).unwrap())
+            .stage(Interpreter::new(&sentences).unwrap());
+
+        let input = "Le chat dort. Il rêve";
+        let nodes = pipeline.run(input).unwrap();
+
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0].children.len(), 4);
+        assert_eq!(nodes[0].text, "Le chat dort.");
+        assert_eq!(&input[nodes[0].start..nodes[0].end], "Le chat dort.");
+        assert_eq!(&input[nodes[1].start..nodes[1].end], "Il rêve");
+    }
+
+    #[test]
+    fn nondeterminism() {
+
+        use crate::nfa::Policy;
+
+        let rules = "
+            sep(' ');
+            upper('M');
+            abbr('M.');
+            dot('.');
+            Fo:{ sep = ^Sep; dot = PoncForte | .; @ = .; };
+            Abbr:{ sep = ^Sep; };
+            PoncForte:{ sep = ^Sep; };
+            Sep*:{ sep = ^.; upper = Fo; abbr = Abbr; @ = Fo; };
+        ";
+
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        assert!(sym.errors.is_empty());
+
+        let interpreter = Interpreter::new(&sym).unwrap();
+        let texts = |nodes: Vec<crate::interpreter::Node>| nodes.into_iter().map(|x| x.text).collect::<Vec<_>>();
+
+        let (nodes, ambiguities) = interpreter.simulate("M. Dupont", Policy::FirstDeclared).unwrap();
+        assert_eq!(texts(nodes), vec!["M", ".", "Dupont"]);
+        assert_eq!((ambiguities[0].start, ambiguities[0].end), (0, 3));
+
+        let (nodes, _) = interpreter.simulate("M. Dupont", Policy::LongestMatch).unwrap();
+        assert_eq!(texts(nodes), vec!["M.", "Dupont"]);
+
+        assert!(interpreter.simulate("M. Dupont", Policy::Error).is_err());
+        assert!(interpreter.simulate("Le chat", Policy::Error).is_ok());
+
+        // When every run fails, the error is the one met furthest, then by the run preferred
+        let sym = Symbols::parser(Scanner::new("a('a'); b('b'); Fo*:{ a = A | B | C; }; A:{ }; B:{ b = .; }; C:{ };", "rules"), false);
+        let interpreter = Interpreter::new(&sym).unwrap();
+        let error = |input: &str| interpreter.simulate(input, Policy::FirstDeclared).map(|_| ()).unwrap_err().to_string();
+        assert_eq!(error("abc"), "input:002 --> No transition from state 'B' for \"c\"\n");
+        assert_eq!(error("aa"), "input:001 --> No transition from state 'A' for \"a\"\n");
+    }
+
+    #[test]
+    fn determinize() {
+
+        use crate::nfa::Policy;
+
+        let rules = "
+            sep(' ');
+            upper('M');
+            abbr('M.');
+            dot('.');
+            letter('a' 'e' 'M');
+            Fo:{ sep = ^Sep; dot = PoncForte; @ = .; };
+            Abbr:{ sep = ^Sep; };
+            PoncForte:{ sep = ^Sep; };
+            Sep*:{ sep = ^.; upper = Fo; abbr = Abbr; letter = Fo; @ = Fo; };
+        ";
+
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        let det = sym.determinize().unwrap();
+
+        // A single transition per token in every state
+        for obj in det.objects.values() {
+            let mut seen = Vec::new();
+            for tr in obj.transitions.iter() {
+                let values = det.objects.get(&tr.origin).map_or(vec![tr.origin.clone()], |x| x.values.iter().map(|&i| det.values[i].0.clone()).collect());
+                for value in values {
+                    assert!(!seen.contains(&value));
+                    seen.push(value);
+                }
             }
-            
         }
 
-        let sym = Symbols::parser(scan, true);
+        let original = Interpreter::new(&sym).unwrap();
+        let determinized = Interpreter::new(&det).unwrap();
+        for input in ["M. Dupont", "Le chat. Il dort.", "a e M", "eM. a"] {
+            assert_eq!(original.run(input).unwrap(), determinized.run(input).unwrap());
+        }
+
+        // States read the same way are merged, as long as every run writes the same nodes
+        let mut sym = Symbols::parser(Scanner::new("sep(' '); dot('.'); letter('a' 'b' 'c'); A:{ sep = ^Sep; letter = .; }; B:{ dot = Dot; letter = .; }; Dot:{ sep = ^Sep; }; Sep*:{ sep = ^.; letter = A | B; };", "rules"), false);
+        for id in ["A", "B"] {
+            sym.objects.get_mut(id).unwrap().label = Some(String::from("Fo"));
+        }
+        let det = sym.determinize().unwrap();
+        assert!(det.objects.values().any(|x| x.origins.len() > 1));
+        let original = Interpreter::new(&sym).unwrap();
+        let determinized = Interpreter::new(&det).unwrap();
+        for input in ["ab c", "ab. c", "c ba. a"] {
+            assert_eq!(original.simulate(input, Policy::FirstDeclared).unwrap().0, determinized.run(input).unwrap());
+        }
+
+        // Otherwise the merged state could only follow one of the runs
+        let sym = Symbols::parser(Scanner::new("sep(' '); dot('.'); Fo:{ sep = ^Sep; dot = PoncForte | .; @ = .; }; PoncForte:{ sep = ^Sep; }; Sep*:{ sep = ^.; @ = Fo; };", "rules"), false);
+        assert_eq!(sym.determinize().err().map(|e| e.to_string()), Some(String::from("rules:001:038 --> Cannot determinize the machine because of a choice between different outputs on '.'\n")));
+    }
+
+    #[test]
+    fn minimize() {
+
+        use crate::nfa::Policy;
+
+        let rules = "
+            sep(' ');
+            upper('M');
+            abbr('M.');
+            dot('.');
+            letter('a' 'e');
+            Fo:{ sep = ^Sep; dot = PoncForte | .; @ = .; };
+            Abbr:{ sep = ^Sep; };
+            PoncForte:{ sep = ^Sep; };
+            Sep*:{ sep = ^.; upper = Fo; abbr = Abbr; letter = Fo | Sep; @ = Fo; };
+            Lost:{ @ = Fo; };
+        ";
+
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        let (_, report) = sym.minimize().unwrap();
+
+        let ids = |groups: &Vec<Vec<(String, super::scanner::Pos)>>| groups.iter().map(|x| x.iter().map(|y| y.0.clone()).collect::<Vec<_>>()).collect::<Vec<_>>();
+        assert!(report.merged.is_empty());
+        assert_eq!(ids(&report.equivalent), vec![vec!["Abbr", "PoncForte"]]);
+        assert_eq!(report.unreachable[0].0, "Lost");
+        assert!(report.to_string().contains("'PoncForte' behaves as 'Abbr'"));
+
+        // Determinized states reading the same way are merged
+        let rules = "
+            sep(' ');
+            dot('.');
+            letter('a' 'b' 'c');
+            A:{ sep = ^Sep; letter = .; };
+            B:{ dot = Dot; letter = .; };
+            C:{ sep = ^Sep; dot = Dot; letter = .; };
+            Dot:{ sep = ^Sep; };
+            Sep*:{ sep = ^.; letter = A | B; dot = C; };
+        ";
+
+        let mut sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        for id in ["A", "B", "C"] {
+            sym.objects.get_mut(id).unwrap().label = Some(String::from("Fo"));
+        }
+        let det = sym.determinize().unwrap();
+        let (min, report) = det.minimize().unwrap();
+        assert!(!report.merged.is_empty());
+        assert!(min.objects.values().filter(|x| !x.transitions.is_empty()).count() < det.objects.values().filter(|x| !x.transitions.is_empty()).count());
+
+        let original = Interpreter::new(&sym).unwrap();
+        let minimized = Interpreter::new(&min).unwrap();
+        for input in ["ab. ca", ".a c", "cb. .b"] {
+            assert_eq!(original.simulate(input, Policy::FirstDeclared).unwrap().0, minimized.run(input).unwrap());
+        }
+
+        // Popping back to '.' goes to the state that pushed, not to the state popping
+        let rules = "
+            sep(' ');
+            dot('.');
+            upper('L' 'I');
+            digit('1' '2');
+            Doc*:{ sep = ^.; @ = >Sentence; };
+            Sentence:{ sep = ^Sep; dot = %<.; @ = .; };
+            Sep:{ sep = ^.; dot = %<.; upper = Title; digit = Name; @ = Word; };
+            Word:{ sep = ^Sep; dot = %<.; @ = .; };
+            Name:{ sep = ^Sep; dot = %<Doc; @ = .; };
+            Title:{ sep = ^Sep; dot = %<.; @ = .; };
+        ";
+
+        let mut sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        for id in ["Word", "Name", "Title"] {
+            sym.objects.get_mut(id).unwrap().label = Some(String::from("Fo"));
+        }
+        let (min, report) = sym.minimize().unwrap();
+        assert_eq!(ids(&report.merged), vec![vec!["Word", "Title"]]);
+
+        let original = Interpreter::new(&sym).unwrap();
+        let minimized = Interpreter::new(&min).unwrap();
+        for input in ["Le chat. Il dort. Lui.", "Il en a 12."] {
+            assert_eq!(original.run(input).unwrap(), minimized.run(input).unwrap());
+        }
+    }
+
+    #[test]
+    fn equivalence() {
+
+        use crate::equivalence::Verdict;
+
+        let old = "
+            sep(' ');
+            dot('.');
+            Fo:{ sep = ^Sep; dot = PoncForte; @ = .; };
+            PoncForte:{ sep = ^Sep; dot = .; @ = Fo; };
+            Sep*:{ sep = ^.; dot = PoncForte; @ = Fo; };
+        ";
+        let refactored = "
+            sep(' ');
+            dot('.');
+            Word:{ sep = ^Space; dot = Punct; @ = .; };
+            Punct:{ sep = ^Space; dot = .; @ = Word; };
+            Space*:{ sep = ^.; dot = Punct; @ = Word; };
+        ";
+        let changed = "
+            sep(' ');
+            dot('.');
+            Fo:{ sep = ^Sep; dot = PoncForte; @ = .; };
+            PoncForte:{ sep = ^Sep; dot = PoncForte; @ = Fo; };
+            Sep*:{ sep = ^.; dot = PoncForte; @ = Fo; };
+        ";
+
+        let old = Symbols::parser(Scanner::new(old, "old"), false);
+        let refactored = Symbols::parser(Scanner::new(refactored, "refactored"), false);
+        let changed = Symbols::parser(Scanner::new(changed, "changed"), false);
+
+        let old = Interpreter::new(&old).unwrap();
+        let changed = Interpreter::new(&changed).unwrap();
+        let refactored = Interpreter::new(&refactored).unwrap();
+
+        // Same segmentation, but not the same node names
+        assert!(matches!(old.equivalent(&refactored, 16).unwrap(), Verdict::Different(x) if x.input == "."));
+
+        let renamed = "
+            sep(' ');
+            dot('.');
+            Fo:{ sep = ^Space; dot = PoncForte; @ = .; };
+            PoncForte:{ sep = ^Space; dot = .; @ = Fo; };
+            Space*:{ sep = ^.; dot = PoncForte; @ = Fo; };
+        ";
+        let renamed = Symbols::parser(Scanner::new(renamed, "renamed"), false);
+        assert!(matches!(old.equivalent(&Interpreter::new(&renamed).unwrap(), 16).unwrap(), Verdict::Equivalent));
+
+        match old.equivalent(&changed, 16).unwrap() {
+            Verdict::Different(x) => {
+                assert_eq!(x.input, "..");
+                assert_eq!(x.old.unwrap().len(), 1);
+                assert_eq!(x.new.unwrap().len(), 2);
+            },
+            verdict => panic!("{}", verdict)
+        }
+    }
+
+    #[test]
+    fn diff() {
+
+        use crate::diff::Change;
+
+        let old = "
+            sep(' ');
+            dot('.');
+            Fo:{ sep = ^Sep; dot = PoncForte; @ = .; };
+            PoncForte:{ sep = ^Sep; @ = Fo; };
+            Sep*:{ sep = ^.; @ = Fo; };
+        ";
+        // Same machine with other formatting and comments, a litteral more and a few changed transitions
+        let new = "
+            sep(' ' '\t');
+            dot('.');
+
+            # Words
+            Fo:{
+                sep = ^Sep;
+                dot = %PoncForte;
+                @ = .;
+            };
+            PoncForte:{ sep = ^Sep; dot = .; @ = Fo; };
+            Sep*:{ sep = ^.; @ = Fo; };
+            End:{};
+        ";
+
+        let old = Symbols::parser(Scanner::new(old, "old"), false);
+        let new = Symbols::parser(Scanner::new(new, "new"), false);
+        assert!(new.errors.is_empty());
+
+        let changes = old.diff(&new);
+        assert_eq!(changes, vec![
+            Change::StateAdded { state: String::from("End") },
+            Change::TransitionChanged { state: String::from("Fo"), on: String::from("dot"), old: String::from(" = PoncForte"), new: String::from(" = %PoncForte") },
+            Change::TransitionAdded { state: String::from("PoncForte"), on: String::from("dot"), transition: String::from(" = .") },
+            Change::LitteralAdded { set: String::from("sep"), litteral: String::from("\t") }
+        ]);
+
+        assert_eq!(changes[1].to_string(), "~ 'Fo' on dot = PoncForte, now = %PoncForte\n");
+        assert!(serde_json::to_string(&changes).unwrap().contains("\"change\":\"litteral_added\""));
+        assert!(old.diff(&old).is_empty());
+    }
+
+    #[test]
+    fn enumerate() {
+
+        use crate::enumerate::Reach;
+
+        let rules = "
+            sep(' ');
+            dot('.');
+            Fo:{ sep = ^Sep; dot = PoncForte; @ = .; };
+            PoncForte:{ sep = ^Sep; };
+            Sep*:{ sep = ^.; @ = Fo; };
+            Lost:{ @ = .; };
+        ";
+
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        let interpreter = Interpreter::new(&sym).unwrap();
+
+        let inputs: Vec<String> = interpreter.enumerate(2).unwrap().into_iter().map(|x| x.0).collect();
+        assert_eq!(inputs, vec!["", " ", ".", "x", "  ", " .", " x", ". ", "..", ".x", "x ", "x.", "xx"]);
+
+        assert_eq!(interpreter.reach(None, 8).unwrap(), Reach::Input(String::new()));
+        assert_eq!(interpreter.reach(Some("PoncForte"), 8).unwrap(), Reach::Input(String::from("..")));
+        assert_eq!(interpreter.reach(Some("Lost"), 8).unwrap(), Reach::Never);
+        assert!(interpreter.reach(Some("Missing"), 8).is_err());
 
-        
+        let samples = interpreter.sample(5, 8, 42).unwrap();
+        assert!(!samples.is_empty());
+        for (input, nodes) in samples.iter() {
+            assert!(input.chars().count() <= 8);
+            assert_eq!(&interpreter.run(input.as_str()).unwrap(), nodes);
+        }
+        assert_eq!(samples, interpreter.sample(5, 8, 42).unwrap());
+    }
+
+    #[test]
+    fn snapshot() {
+
+        use crate::pipeline::Pipeline;
+        use crate::testing::{assert_snapshots, snapshots, Edit, Outcome};
+
+        let dir = temp_dir("snapshot");
+        fs::write(dir.join("a.txt"), "le chat dort").unwrap();
+        fs::write(dir.join("b.txt"), "il rêve").unwrap();
+
+        let words = Symbols::parser(Scanner::new("sep(' '); Fo*:{ sep = ^Fo; @ = .; };", "words"), false);
+        let pipeline = Pipeline::new().stage(Interpreter::new(&words).unwrap());
+
+        let outcomes = |bless| snapshots(&pipeline, &dir, bless).unwrap().into_iter().map(|x| x.outcome).collect::<Vec<_>>();
+        assert_eq!(outcomes(false), vec![Outcome::Missing, Outcome::Missing]);
+        assert_eq!(outcomes(true), vec![Outcome::Blessed, Outcome::Blessed]);
+        assert_eq!(outcomes(false), vec![Outcome::Passed, Outcome::Passed]);
+        assert_snapshots(&pipeline, &dir);
+
+        // Spaces are now kept at the end of the words
+        let words = Symbols::parser(Scanner::new("sep(' '); Fo*:{ sep = %Fo; @ = .; };", "words"), false);
+        let pipeline = Pipeline::new().stage(Interpreter::new(&words).unwrap());
+
+        let snapshots = snapshots(&pipeline, &dir, false).unwrap();
+        assert_eq!(snapshots[1].outcome, Outcome::Changed(vec![
+            (1, Edit::Missing(String::from("Fo\t\"il\""))),
+            (2, Edit::Unexpected(String::from("Fo\t\"il \"")))
+        ]));
+        assert!(snapshots[0].to_string().starts_with(&format!("{}: changed\n", dir.join("a.txt").display())));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn coverage() {
+
+        use crate::coverage::Coverage;
+
+        let rules = "
+            sep(' ' '\t');
+            dot('.');
+            Fo*:{ sep = ^Sep; dot = PoncForte; @ = .; };
+            PoncForte:{ sep = ^Sep; };
+            Sep:{ sep = ^.; @ = Fo; };
+            Lost:{ @ = .; };
+        ";
+
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        let interpreter = Interpreter::new(&sym).unwrap();
+
+        let mut coverage = Coverage::new();
+        interpreter.run_with("le chat", &mut coverage).unwrap();
+        interpreter.run_with("fin.", &mut coverage).unwrap();
+
+        let report = coverage.report(&sym);
+        assert_eq!((report.states, report.transitions, report.sets, report.litterals), ((3, 4), (4, 7), (2, 2), (2, 3)));
+
+        let uncovered: Vec<String> = report.uncovered.iter().map(|x| format!("{}{} {}", x.loc, x.kind, x.what)).collect();
+        assert_eq!(uncovered, vec![
+            "rules:002:021 --> litteral \"\\t\" of 'sep'",
+            "rules:005:025 --> transition 'PoncForte: sep = ^Sep'",
+            "rules:006:019 --> transition 'Sep: sep = ^.'",
+            "rules:007:013 --> state 'Lost'",
+            "rules:007:020 --> transition 'Lost: @ = .'"
+        ]);
+        assert!(report.to_string().ends_with("states 3/4, transitions 4/7, sets 2/2, litterals 2/3\n"));
+
+        let annotated = coverage.annotate(&sym, "rules", rules);
+        assert!(annotated.lines().nth(3).unwrap().starts_with("      8 | "));
+        assert!(annotated.lines().nth(6).unwrap().ends_with("<-- never covered: 'Lost', 'Lost: @ = .'"));
+
+        // Alternatives to the same state are counted apart, a single-state run only taking the first
+        let rules = "dot('.'); Fo*:{ dot = PoncForte | %PoncForte; @ = .; }; PoncForte:{ @ = Fo; };";
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        let interpreter = Interpreter::new(&sym).unwrap();
+
+        let mut coverage = Coverage::new();
+        interpreter.run_with("fin.", &mut coverage).unwrap();
+
+        let report = coverage.report(&sym);
+        assert_eq!(report.transitions, (2, 4));
+        let uncovered: Vec<String> = report.uncovered.iter().map(|x| format!("{} {}", x.kind, x.what)).collect();
+        assert_eq!(uncovered, vec!["transition 'Fo: dot = %PoncForte'", "transition 'PoncForte: @ = Fo'"]);
+    }
+
+    #[test]
+    fn profile() {
+
+        use crate::profile::Profile;
+
+        let dir = temp_dir("profile");
+        fs::write(dir.join("numbers.stm"), "digit('0' '1' '2' '3' '4' '5' '6' '7' '8' '9'); Int*:{ digit = .; };").unwrap();
+
+        let rules = "
+            sep(' ');
+            digit('0' '1' '2' '3' '4' '5' '6' '7' '8' '9');
+            Fo:{ sep = ^Sep; @ = .; };
+            Number:call \"numbers.stm\"{ sep = ^Sep; @ = Fo; };
+            Sep*{ sep = ^.; digit = Number; @ = Fo; };
+        ";
+
+        let file = dir.join("main.stm").to_string_lossy().to_string();
+        let sym = Symbols::parser(Scanner::new(rules, &file), false);
+        let interpreter = Interpreter::new(&sym).unwrap();
+
+        let mut profile = Profile::new();
+        interpreter.run_with("pi 314 ok", &mut profile).unwrap();
+        interpreter.run_with("zéro", &mut profile).unwrap();
+
+        let counts: Vec<_> = profile.states().iter().map(|x| (x.id.as_str(), x.entries, x.tokens, x.fallbacks)).collect();
+        for state in [("Fo", 3, 6, 5), ("Sep", 4, 4, 3), ("Number", 1, 1, 0), ("Int", 1, 3, 0)] {
+            assert!(counts.contains(&state), "{:?}", counts);
+        }
+        let fo = profile.states().into_iter().find(|x| x.id == "Fo").unwrap().clone();
+        assert_eq!(fo.average_length(), 7.0 / 6.0);
+
+        // The digits of the caller and of the machine called are counted apart
+        let numbers = dir.join("numbers.stm").to_string_lossy().to_string();
+        assert_eq!(profile.sets(), vec![(numbers.as_str(), "digit", 3), (file.as_str(), "sep", 2), (file.as_str(), "digit", 1)]);
+        assert_eq!(profile.transitions()[0], ("Fo: @ = .", 5));
+
+        let folded = profile.folded();
+        let stacks: Vec<&str> = folded.lines().map(|x| x.rsplit_once(' ').unwrap().0).collect();
+        assert_eq!(stacks, vec!["Fo", "Number", "Number;Int", "Sep"]);
+        assert!(profile.to_string().starts_with("state "));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compile() {
+
+        let same = |sym: &Symbols, inputs: &[&str]| {
+            assert!(sym.errors().is_empty());
+            let interpreter = Interpreter::new(sym).unwrap();
+            let compiled = interpreter.compile();
+            for input in inputs {
+                let expected = interpreter.run(*input).map_err(|e| e.to_string());
+                assert_eq!(compiled.run(*input).map_err(|e| e.to_string()), expected, "{:?}", input);
+            }
+        };
+
+        let sym = Symbols::from_file("test.txt", false).unwrap();
+        same(&sym, &["l'homme est là.", "Oui, bien sûr ?!", "Fin... ou pas ! « bla \" tritri ' tu \\ \n \n »", "", "  ", "?"]);
+
+        let rules = "
+            sep(' ');
+            upper('L' 'C');
+            dot('.' '..' '...');
+            Doc*:{ sep = ^.; @ = >Sentence; };
+            Sentence:[type=\"s\"]{ sep = ^Sep; dot &(sep upper) = %<.; dot !($) = .; dot = %<. [last=\"yes\"]; @ = .; };
+            Sep:{ sep = ^.; dot = %<.; @ = Sentence; };
+        ";
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        same(&sym, &["M.Dupont vient. Le chat", "Fin... Là", "fin.", ". .", "a b c"]);
+
+        let interpreter = Interpreter::new(&sym).unwrap().max_depth(0);
+        assert_eq!(interpreter.compile().run("Fin").map_err(|e| e.to_string()), interpreter.run("Fin").map_err(|e| e.to_string()));
+
+        // Sets mixing ASCII, other characters in ranges, and litterals of several characters
+        let rules = "
+            sep(' ' '\u{a0}' '\u{202f}');
+            dash('-' '‐' '‑' '‒' '–' '—');
+            dots('...' '..' '…' '?!' 'etc.');
+            Fo:{ sep = ^Sep; dash = Dash; dots = Dots; @ = .; };
+            Dash:{ sep = ^Sep; dash = .; @ = Fo; };
+            Dots:{ sep = ^Sep; dots = Dots; @ = Fo; };
+            Sep*:{ sep = ^.; dash = Dash; dots = Dots; @ = Fo; };
+        ";
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        same(&sym, &["Oui — enfin… non\u{a0}?! etc. a-b ‒ c.. d...", "–—‐ ..x", "é\u{202f}?"]);
+
+        // Runs read in bulk by states looping on themselves, through '@' or through a set
+        let rules = "
+            sep(' ' ' -' '\t');
+            nb('0' '1' '2');
+            star('*');
+            Fo:{ sep = ^Sep; nb = Nb; @ = .; };
+            Nb:{ nb = .; sep = ^Sep; @ = Fo; };
+            Quiet:{ nb = ^.; sep = Sep; @ = ^.; };
+            Sep*:{ sep = ^.; nb = Nb; star = Quiet; @ = Fo; };
+        ";
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        same(&sym, &["le  chat -  dort\t\t 0120 x12 ééé  -é", "  - -- 12é", "* x12é3 ", "mot"]);
+
+        let sym = Symbols::parser(Scanner::new("sep(' '); apos('’'); shy('\u{ad}'); Fo*:{ sep = ^Fo; apos -> \"'\" = %Fo; shy -> \"\" = .; @ = .; };", "rules"), false);
+        same(&sym, &["l’hom\u{ad}me", "’’ ’"]);
+
+        // Calls, and nodes read from a previous machine
+        let dir = temp_dir("compile");
+        fs::write(dir.join("numbers.stm"), "digit('0' '1' '2' '3' '4' '5' '6' '7' '8' '9'); comma(','); Int*:{ digit = .; comma &(digit) = %Frac; }; Frac:{ digit = .; };").unwrap();
+
+        let rules = "
+            sep(' ');
+            dot('.');
+            digit('0' '1' '2' '3' '4' '5' '6' '7' '8' '9');
+            Fo:{ sep = ^Sep; dot = Dot; @ = .; };
+            Dot:{ sep = ^Sep; @ = Fo; };
+            Number:call \"numbers.stm\"{ sep = ^Sep; @ = Fo; };
+            Sep*:{ sep = ^.; digit = Number; dot = Dot; @ = Fo; };
+        ";
+        let file = dir.join("main.stm").to_string_lossy().to_string();
+        let tokens = Symbols::parser(Scanner::new(rules, &file), false);
+        same(&tokens, &["pi 3,14 ok", "3, 14", "x 12. y"]);
+
+        let sentences = Symbols::parser(Scanner::new("end(Dot); dot('.'); Sentence*:{ end = %Sentence; dot = .; @ = .; };", "sentences"), false);
+        let nodes = Interpreter::new(&tokens).unwrap().run("pi vaut 3,14. Il rêve").unwrap();
+        let interpreter = Interpreter::new(&sentences).unwrap();
+        assert_eq!(interpreter.compile().run(nodes.as_slice()).unwrap(), interpreter.run(nodes.as_slice()).unwrap());
+
+        let compiled = Interpreter::new(&tokens).unwrap().compile();
+        assert_eq!(compiled.state_count(), 7);
+        assert_eq!(compiled.set_count(), 3);
+        assert_eq!(compiled.state_id("Number").and_then(|x| compiled.state_name(x)), Some("Number"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn borrowed() {
+
+        use std::borrow::Cow;
+        use crate::borrowed::BorrowedNode;
+
+        let same = |sym: &Symbols, input: &str| {
+            let interpreter = Interpreter::new(sym).unwrap();
+            let compiled = interpreter.compile();
+            let nodes = compiled.run_borrowed(input).map(|x| x.into_iter().map(BorrowedNode::into_owned).collect::<Vec<_>>());
+            assert_eq!(nodes.map_err(|e| e.to_string()), interpreter.run(input).map_err(|e| e.to_string()));
+        };
+
+        let sym = Symbols::from_file("test.txt", false).unwrap();
+        same(&sym, "l'homme est là. Oui, bien sûr ?!");
+
+        let rules = "
+            sep(' ');
+            apos('’');
+            shy('\u{ad}');
+            dash('-');
+            Fo*:[pos=\"WORD\"]{ sep = ^Fo; apos -> \"'\" = %Fo; shy -> \"\" = .; dash = ^.; @ = .; };
+        ";
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        same(&sym, "l’hom\u{ad}me porte-clé tout");
+
+        let compiled = Interpreter::new(&sym).unwrap().compile();
+        let input = String::from("l’hom\u{ad}me porte-clé tout");
+        let nodes = compiled.run_borrowed(&input).unwrap();
+
+        // Text read as it is stays in the input, rewritten or skipping part of it is owned
+        let texts: Vec<(&str, bool)> = nodes.iter().map(|x| (x.text.as_ref(), matches!(x.text, Cow::Borrowed(_)))).collect();
+        assert_eq!(texts, vec![("l'", false), ("homme", false), ("porteclé", false), ("tout", true)]);
+        assert!(std::ptr::eq(nodes[3].text.as_ref(), &input[nodes[3].start..nodes[3].end]));
+        assert_eq!(nodes[3].attributes.get("pos"), Some(&"WORD"));
+    }
+
+    #[test]
+    fn parallel() {
+
+        use crate::interpreter::Node;
+
+        // Chunks between split points, read one after the other, give the same nodes as a single run
+        let same = |sym: &Symbols, input: &str, size: usize| -> Vec<usize> {
+            let interpreter = Interpreter::new(sym).unwrap();
+            let compiled = interpreter.compile();
+            let points = compiled.split_points(input, size);
+
+            let bounds: Vec<(usize, usize)> = std::iter::once(0).chain(points.iter().copied()).zip(points.iter().copied().chain(std::iter::once(input.len()))).collect();
+            let chunks: Result<Vec<Node>, String> = bounds.iter().try_fold(Vec::new(), |mut out, &(from, to)| {
+                out.extend(compiled.chunk::<Node>(input, from, to).map_err(|e| e.to_string())?);
+                Ok(out)
+            });
+            let expected = interpreter.run(input).map_err(|e| e.to_string());
+            assert_eq!(chunks, expected, "{:?}", points);
+
+            #[cfg(feature = "rayon")]
+            assert_eq!(compiled.run_parallel(input, size).map_err(|e| e.to_string()), expected);
+
+            points
+        };
+
+        // The separators lead back to the entry state from everywhere, except inside the longer litteral of PoncFaible
+        let sym = Symbols::from_file("test.txt", false).unwrap();
+        assert_eq!(Interpreter::new(&sym).unwrap().compile().split_set_names(), vec!["sep"]);
+        let input = "Oui, bien sûr ?! bla \" tritri ' tu \\ \n \n l'homme est là.";
+        assert_eq!(same(&sym, input, 1), vec![5, 10, 15, 18, 42, 50, 54]);
+        same(&sym, input, 20);
+        same(&sym, "", 1);
+        same(&sym, "a   b", 1);
+
+        // Sets leading elsewhere, or written, are no split points
+        let rules = "
+            sep(' ');
+            nl('\n');
+            dot('.');
+            Fo:{ sep = ^Sep; nl = ^Sep; dot = %Fo; @ = .; };
+            Sep*:{ sep = ^.; nl = ^.; dot = Fo; @ = Fo; };
+        ";
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        assert_eq!(Interpreter::new(&sym).unwrap().compile().split_set_names(), vec!["sep", "nl"]);
+        same(&sym, "un. deux\ntrois . quatre", 3);
+
+        let rules = "
+            sep(' ');
+            Fo:{ sep = Sep; @ = .; };
+            Sep*:{ sep = ^.; @ = Fo; };
+        ";
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        assert!(Interpreter::new(&sym).unwrap().compile().split_set_names().is_empty());
+        assert!(same(&sym, "un deux trois", 1).is_empty());
+
+        // Errors are the ones of the first chunk failing
+        let rules = "
+            sep(' ');
+            Fo:{ sep = ^Sep; };
+            Sep*:{ sep = ^.; @ = Fo; };
+        ";
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        same(&sym, "a bc de", 1);
+    }
+
+    #[test]
+    fn cursor() {
+
+        use std::sync::Arc;
+        use crate::interpreter::Node;
+
+        let sym = Symbols::from_file("test.txt", false).unwrap();
+        let machine = Arc::new(Interpreter::new(&sym).unwrap().compile());
+
+        // A single machine serves several threads, each run having its own cursor
+        let inputs = ["l'homme est là.", "Oui, bien sûr ?!", "", "  fin"];
+        let threads: Vec<_> = inputs.into_iter().map(|input| {
+            let machine = Arc::clone(&machine);
+            std::thread::spawn(move || machine.cursor(input).collect::<Result<Vec<Node>, _>>().map_err(|e| e.to_string()))
+        }).collect();
+
+        for (input, thread) in inputs.iter().zip(threads) {
+            assert_eq!(thread.join().unwrap(), machine.run(*input).map_err(|e| e.to_string()));
+        }
+
+        // Nodes are given as soon as they are written, then the error stopping the run
+        let mut cursor = machine.cursor("Oui, bien");
+        assert_eq!(cursor.state(), Some("Sep"));
+        assert_eq!(cursor.next().map(|x| x.unwrap().text), Some(String::from("Oui")));
+        assert_eq!(cursor.position(), 4);
+        assert_eq!(cursor.by_ref().map(|x| x.unwrap().text).collect::<Vec<_>>(), vec![",", "bien"]);
+        assert_eq!(cursor.state(), None);
+
+        let rules = "
+            sep(' ');
+            dot('.');
+            Doc*:{ sep = ^.; dot = Fo; @ = >Sentence; };
+            Sentence:{ sep = ^Sep; dot = %<.; @ = .; };
+            Sep:{ sep = ^.; dot = %<.; @ = Sentence; };
+            Fo:{ sep = ^Doc; };
+        ";
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        let machine = Interpreter::new(&sym).unwrap().compile();
+        for input in ["Un chat. Deux", "Un. Deux.", "Un. .x"] {
+            assert_eq!(machine.cursor(input).collect::<Result<Vec<Node>, _>>().map_err(|e| e.to_string()), machine.run(input).map_err(|e| e.to_string()));
+        }
+
+        let mut cursor = machine.cursor("Un. .x");
+        assert_eq!(cursor.next().map(|x| x.unwrap().children.len()), Some(1));
+        assert!(cursor.next().unwrap().is_err());
+        assert!(cursor.next().is_none());
+    }
+
+    #[test]
+    fn stream() {
+
+        use crate::interpreter::Node;
+
+        // Gives a single byte at a time, so that litterals, conditions and characters are cut between reads
+        struct Drip<'a>(&'a [u8]);
+
+        impl std::io::Read for Drip<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let Some((first, rest)) = self.0.split_first() else { return Ok(0) };
+                buf[0] = *first;
+                self.0 = rest;
+                Ok(1)
+            }
+        }
+
+        let same = |machine: &crate::compile::CompiledMachine, input: &str| {
+            let streamed = machine.stream(Drip(input.as_bytes())).collect::<Result<Vec<Node>, _>>().map_err(|e| e.to_string());
+            assert_eq!(streamed, machine.run(input).map_err(|e| e.to_string()));
+        };
+
+        let sym = Symbols::from_file("test.txt", false).unwrap();
+        let machine = Interpreter::new(&sym).unwrap().compile();
+        for input in ["l'homme est là.", "Fin... ou pas ! « bla \" tritri ' tu \\ \n \n » l’homme", "", "?"] {
+            same(&machine, input);
+        }
+
+        // Lookahead sees the text past the token, and the end of the stream
+        let rules = "
+            sep(' ');
+            dot('.' '...');
+            upper('L' 'I');
+            Doc*:{ sep = ^.; @ = >Sentence; };
+            Sentence:{ sep = ^Sep; dot &(sep upper) = %<.; dot !($) = .; dot = %<.; @ = .; };
+            Sep:{ sep = ^.; dot = %<.; @ = Sentence; };
+        ";
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        let machine = Interpreter::new(&sym).unwrap().compile();
+        for input in ["M. Le chat. Il dort... Fin.", "a.b. c", "Fin...", "Fin. l"] {
+            same(&machine, input);
+        }
+
+        // Text read is dropped along the way, offsets being kept in the whole text
+        let input = "Le chat dort. Il rêve… ".repeat(2000);
+        same(&machine, &input);
+        let mut stream = machine.stream(input.as_bytes());
+        let last = stream.by_ref().last().unwrap().unwrap();
+        assert_eq!((last.start, last.end, stream.position()), (input.len() - 12, input.len(), input.len()));
+
+        let error = |bytes: &[u8]| machine.stream(bytes).find_map(Result::err).map(|e| e.to_string());
+        assert_eq!(error(b"ab \xFF c"), Some(String::from("input:003 --> Cannot read the input: Invalid UTF-8 text\n")));
+        assert_eq!(error(&"ab é".as_bytes()[..4]), Some(String::from("input:003 --> Cannot read the input: Invalid UTF-8 text\n")));
+
+        // Called machines may read any amount of text, which is then read whole
+        let dir = temp_dir("stream");
+        fs::write(dir.join("numbers.stm"), "digit('0' '1' '2'); comma(','); Int*:{ digit = .; comma &(digit) = %Frac; }; Frac:{ digit = .; };").unwrap();
+        let rules = "sep(' '); digit('0' '1' '2'); Fo:{ sep = ^Sep; @ = .; }; Number:call \"numbers.stm\"{ sep = ^Sep; @ = Fo; }; Sep*:{ sep = ^.; digit = Number; @ = Fo; };";
+        let sym = Symbols::parser(Scanner::new(rules, &dir.join("main.stm").to_string_lossy()), false);
+        let machine = Interpreter::new(&sym).unwrap().compile();
+        for input in ["pi 2,10 ok", "12, 1"] {
+            same(&machine, input);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stmc() {
+
+        use crate::compile::CompiledMachine;
+        use crate::stmc::StmcError;
+
+        let sym = Symbols::from_file("test.txt", false).unwrap();
+        let machine = Interpreter::new(&sym).unwrap().compile();
+
+        let rules = "
+            sep(' ');
+            dot('.' '..' '...');
+            apos('’');
+            upper('L' 'C');
+            Doc*:{ sep = ^.; @ = >Sentence; };
+            Sentence:[type=\"s\"]{ sep = ^Sep; apos -> \"'\" = %.; dot &(sep upper) = %<.; dot !($) = .; dot = %<. [last=\"yes\"]; @ = .; };
+            Sep:{ sep = ^.; dot = %<.; @ = Sentence; };
+        ";
+        let other = Symbols::parser(Scanner::new(rules, "rules"), false);
+        let nested = Interpreter::new(&other).unwrap().compile();
+
+        // Loaded machines write the same nodes, and are saved again as the same bytes
+        for (machine, inputs) in [(&machine, ["l'homme est là.", "Oui, bien sûr ?! bla \" tritri ' tu \\ \n \n »"]), (&nested, ["M.Dupont vient. Le chat", "l’homme... Là"])] {
+            let bytes = machine.to_bytes();
+            let loaded = CompiledMachine::from_bytes(&bytes).unwrap();
+            assert_eq!(loaded.to_bytes(), bytes);
+            assert_eq!((loaded.state_count(), loaded.set_count()), (machine.state_count(), machine.set_count()));
+            for input in inputs {
+                assert_eq!(loaded.run(input).map_err(|e| e.to_string()), machine.run(input).map_err(|e| e.to_string()));
+            }
+        }
+
+        // The text of the machine and the litterals of its sets are read in place, unless the machine is made to own them
+        let bytes = nested.to_bytes();
+        let loaded = CompiledMachine::from_bytes(&bytes).unwrap();
+        let inside = |x: &str| bytes.as_ptr_range().contains(&x.as_ptr());
+        assert!(loaded.names.states.iter().chain(loaded.names.sets.iter()).all(|x| inside(x)));
+        assert!(loaded.states.iter().flat_map(|x| x.attributes.iter()).all(|(k, v)| inside(k) && inside(v)));
+        assert!(loaded.sets.iter().any(|x| x.multi.len() == 2 && x.multi.iter().all(inside) && x.multi.contains("...") && !x.multi.contains(".")));
+        let owned = loaded.into_owned();
+        assert!(owned.names.states.iter().all(|x| !inside(x)));
+        assert_eq!(owned.to_bytes(), bytes);
+
+        // Called machines, and sets of labels read from the nodes of a previous machine
+        let dir = temp_dir("stmc");
+        fs::write(dir.join("numbers.stm"), "digit('0' '1' '2' '3' '4' '5' '6' '7' '8' '9'); comma(','); Int*:{ digit = .; comma &(digit) = %Frac; }; Frac:{ digit = .; };").unwrap();
+        let rules = "
+            sep(' ');
+            digit('0' '1' '2' '3' '4' '5' '6' '7' '8' '9');
+            Fo:{ sep = ^Sep; @ = .; };
+            Number:call \"numbers.stm\"{ sep = ^Sep; @ = Fo; };
+            Sep*:{ sep = ^.; digit = Number; @ = Fo; };
+        ";
+        let tokens = Symbols::parser(Scanner::new(rules, &dir.join("main.stm").to_string_lossy()), false);
+        let sentences = Symbols::parser(Scanner::new("end(Sep); Sentence*:{ end = %Sentence; @ = .; };", "sentences"), false);
+
+        let first = Interpreter::new(&tokens).unwrap().compile();
+        let second = Interpreter::new(&sentences).unwrap().compile();
+        let (first_bytes, second_bytes) = (first.to_bytes(), second.to_bytes());
+        let (first_loaded, second_loaded) = (CompiledMachine::from_bytes(&first_bytes).unwrap(), CompiledMachine::from_bytes(&second_bytes).unwrap());
+        let nodes = first_loaded.run("pi 3,14 ok").unwrap();
+        assert_eq!(nodes, first.run("pi 3,14 ok").unwrap());
+        assert_eq!(second_loaded.run(nodes.as_slice()).unwrap(), second.run(nodes.as_slice()).unwrap());
+
+        let path = dir.join("machine.stmc");
+        machine.to_file(&path).unwrap();
+        assert_eq!(CompiledMachine::from_file(&path).unwrap().to_bytes(), machine.to_bytes());
+
+        // Files that aren't machines, or were written by another version, changed or cut are rejected
+        let bytes = machine.to_bytes();
+        let load = |f: &dyn Fn(&mut Vec<u8>)| {
+            let mut bytes = bytes.clone();
+            f(&mut bytes);
+            CompiledMachine::from_bytes(&bytes).err()
+        };
+
+        assert!(matches!(load(&|x| x[0] = b'X'), Some(StmcError::NotAMachine)));
+        assert!(matches!(load(&|x| x[4] = 1), Some(StmcError::Version(1))));
+        assert!(matches!(load(&|x| *x.last_mut().unwrap() ^= 1), Some(StmcError::Checksum(_, _))));
+        assert!(matches!(load(&|x| x.truncate(100)), Some(StmcError::Truncated(100))));
+        assert!(matches!(load(&|x| x.push(0)), Some(StmcError::Invalid(_, _))));
+        assert!(CompiledMachine::from_bytes(b"STMC").is_err());
+        assert_eq!(crate::stmc::crc32(b"123456789"), 0xCBF43926);
+
+        // Indices out of range are caught even when the checksum matches
+        let error = load(&|x| {
+            x[24..28].copy_from_slice(&1000u32.to_le_bytes());
+            let checksum = crate::stmc::crc32(&x[24..]);
+            x[16..20].copy_from_slice(&checksum.to_le_bytes());
+        });
+        assert_eq!(error.map(|e| e.to_string()), Some(format!("stmc:024 --> No state 1000, there are {}\n", machine.state_count())));
+
+        // Litterals searched in place must be sorted and cut at characters
+        let bytes = nested.to_bytes();
+        let text = bytes.windows(5).position(|x| x == b".....").unwrap();
+        let corrupt = |ends: [u32; 2]| {
+            let mut x = bytes.clone();
+            x[text - 12..text - 8].copy_from_slice(&ends[0].to_le_bytes());
+            x[text - 8..text - 4].copy_from_slice(&ends[1].to_le_bytes());
+            let checksum = crate::stmc::crc32(&x[24..]);
+            x[16..20].copy_from_slice(&checksum.to_le_bytes());
+            CompiledMachine::from_bytes(&x).err().map(|e| e.to_string())
+        };
+        assert_eq!(corrupt([2, 5]), None);
+        assert_eq!(corrupt([3, 5]), Some(format!("stmc:{:0>3} --> Litterals are not sorted\n", text - 4)));
+        assert_eq!(corrupt([5, 2]), Some(format!("stmc:{:0>3} --> Litteral 1 is not in the text of the set\n", text - 4)));
+
+        // Machines each calling one more, far deeper than the stack would allow
+        let level = [&0u32.to_le_bytes()[..], &0u64.to_le_bytes(), &0u32.to_le_bytes(), &0u32.to_le_bytes(), &1u32.to_le_bytes()].concat();
+        let body = level.repeat(100_000);
+        let header = [&b"STMC"[..], &crate::stmc::VERSION.to_le_bytes(), &(body.len() as u64).to_le_bytes(), &crate::stmc::crc32(&body).to_le_bytes(), &0u32.to_le_bytes()].concat();
+        let error = CompiledMachine::from_bytes(&[header, body].concat()).err();
+        assert_eq!(error.map(|e| e.to_string()), Some(String::from("stmc:1560 --> Machines called more than 64 levels deep\n")));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn interchange() {
+
+        let errors = |sym: &Symbols| sym.errors().iter().map(|e| e.to_string()).collect::<Vec<_>>();
+
+        // Documents read back give the same machine, and are written again the same way
+        let sym = Symbols::from_file("test.txt", false).unwrap();
+        let json = sym.to_json();
+        let loaded = Symbols::from_json(&json, "test.json");
+        assert!(loaded.errors().is_empty());
+        assert_eq!(loaded.to_json(), json);
+        assert_eq!(loaded.test_inputs().collect::<Vec<_>>(), sym.test_inputs().collect::<Vec<_>>());
+
+        let input = "Fin... ou pas ! « bla \" tritri ' tu \\ \n \n » l'homme";
+        assert_eq!(Interpreter::new(&loaded).unwrap().run(input).unwrap(), Interpreter::new(&sym).unwrap().run(input).unwrap());
+
+        let rules = "
+            sep(' ');
+            dot('.');
+            upper('L');
+            Doc*:{ sep = ^.; @ = >Sentence; };
+            Sentence:[type=\"s\"]{ sep = ^Sep; dot &(sep upper) = %<.; dot !($) = . | %<.; dot -> \"!\" = %<. [last=\"yes\"]; @ = .; };
+            Sep:{ sep = ^.; dot = %<.; @ = Sentence; };
+        ";
+        let sym = Symbols::parser(Scanner::new(rules, "rules"), false);
+        let json = sym.to_json();
+        assert!(json.contains("\"alternative\": true") && json.contains("\"stack\": \"Pop\""));
+        let loaded = Symbols::from_json(&json, "rules.json");
+        assert_eq!(loaded.to_json(), json);
+        assert_eq!(Interpreter::new(&loaded).unwrap().run("M. Le chat. Fin.").unwrap(), Interpreter::new(&sym).unwrap().run("M. Le chat. Fin.").unwrap());
+
+        // The same checks as for rule files
+        let document = r#"{
+            "version": 1,
+            "objects": [
+                { "id": "sep", "litterals": [" "] },
+                { "id": "Fo", "entry": true, "end": true, "transitions": [
+                    { "on": "sep", "target": "Fo", "writing": "NoWrite" },
+                    { "on": "@", "target": "." },
+                    { "on": "sep", "target": "." },
+                    { "on": "@", "target": "Fo", "alternative": true }
+                ] }
+            ]
+        }"#;
+        assert_eq!(errors(&Symbols::from_json(document, "doc.json")), vec![
+            "doc.json:002:003 --> Duplicate transition: 'sep => . (doc.json:002:001 --> already defined here)'\n",
+            "doc.json:002:004 --> Unattached object specifier: ''alternative''\n"
+        ]);
+
+        // Labels are not states merged into the object; merged states keep the name of their nodes, not where they come from
+        let document = r#"{
+            "version": 1,
+            "objects": [
+                { "id": "sep", "litterals": [" "] },
+                { "id": "Fo", "entry": true, "end": true, "label": "Word", "transitions": [
+                    { "on": "sep", "target": "Fo", "writing": "NoWrite" },
+                    { "on": "@", "target": "." }
+                ] }
+            ]
+        }"#;
+        let loaded = Symbols::from_json(document, "doc.json");
+        assert!(loaded.objects["Fo"].origins.is_empty());
+        assert_eq!(Interpreter::new(&loaded).unwrap().run("le chat").unwrap().iter().map(|x| x.id.as_str()).collect::<Vec<_>>(), vec!["Word", "Word"]);
+        assert_eq!(Symbols::from_json(&loaded.to_json(), "doc.json").to_json(), loaded.to_json());
+
+        let mut sym = Symbols::parser(Scanner::new("sep(' '); dot('.'); letter('a' 'b'); A:{ sep = ^Sep; letter = .; }; B:{ dot = Dot; letter = .; }; Dot:{ sep = ^Sep; }; Sep*:{ sep = ^.; letter = A | B; };", "rules"), false);
+        for id in ["A", "B"] {
+            sym.objects.get_mut(id).unwrap().label = Some(String::from("Fo"));
+        }
+        let det = sym.determinize().unwrap();
+        let loaded = Symbols::from_json(&det.to_json(), "det.json");
+        assert!(det.objects.values().any(|x| x.origins.len() > 1) && loaded.objects.values().all(|x| x.origins.is_empty()));
+        assert_eq!(Interpreter::new(&loaded).unwrap().run("ab. ba").unwrap(), Interpreter::new(&det).unwrap().run("ab. ba").unwrap());
+
+        assert_eq!(errors(&Symbols::from_json(r#"{ "version": 2, "objects": [] }"#, "doc.json")), vec!["doc.json:000:000 --> Malformed document: Schema version 2, while version 1 is expected\n"]);
+        let malformed = errors(&Symbols::from_json("{ \"version\": 1,\n \"objects\": [{ \"id\": \"Fo\", \"kind\": 1 }] }", "doc.json"));
+        assert!(malformed.len() == 1 && malformed[0].starts_with("doc.json:002:") && malformed[0].contains("unknown field `kind`"), "{:?}", malformed);
+
+        // Called machines may be rule files or documents
+        let dir = temp_dir("interchange");
+        let numbers = Symbols::parser(Scanner::new("digit('0' '1' '2'); Int*:{ digit = .; };", "numbers.stm"), false);
+        fs::write(dir.join("numbers.json"), numbers.to_json()).unwrap();
+        let rules = "sep(' '); digit('0' '1' '2'); Fo:{ sep = ^Sep; @ = .; }; Number:call \"numbers.json\"{ sep = ^Sep; @ = Fo; }; Sep*:{ sep = ^.; digit = Number; @ = Fo; };";
+        let main = dir.join("main.stm").to_string_lossy().to_string();
+        let sym = Symbols::parser(Scanner::new(rules, &main), false);
+        assert!(sym.errors().is_empty());
+        fs::write(dir.join("main.json"), sym.to_json()).unwrap();
+        assert!(sym.to_json().contains("\"call\": \"numbers.json\""));
+
+        let loaded = Symbols::from_file(&dir.join("main.json").to_string_lossy(), false).unwrap();
+        assert!(loaded.errors().is_empty());
+        assert_eq!(Interpreter::new(&loaded).unwrap().run("pi 120 ok").unwrap(), Interpreter::new(&sym).unwrap().run("pi 120 ok").unwrap());
+
+        #[cfg(feature = "yaml")]
+        {
+            let sym = Symbols::from_file("test.txt", false).unwrap();
+            let loaded = Symbols::from_yaml(&sym.to_yaml(), "test.yaml");
+            assert!(loaded.errors().is_empty());
+            assert_eq!(loaded.to_json(), sym.to_json());
+        }
 
-        
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,498 @@
+use std::{env, fs, io::Read, path::Path, process::ExitCode};
+
+use stamesp::{compile::CompiledMachine, coverage::Coverage, enumerate::Reach, equivalence::Verdict, export, interpreter::Interpreter, nfa::Policy, parser::Symbols, pipeline::Pipeline, profile::Profile, testing::{self, Outcome}};
+
+const USAGE: &str = "Usage:
+    stamesp run --stage <rules> [--stage <rules>]... [--format text|xml|json] [--policy first|longest|error] [<input>]
+    stamesp compile <rules> [--output <file>]
+    stamesp convert <rules> [--format json|yaml]
+    stamesp lint <rules>
+    stamesp diff <old rules> <new rules> [--length <n>]
+    stamesp diff --symbols <old rules> <new rules> [--format text|json]
+    stamesp enumerate <rules> [--length <n>] [--sample <count>] [--seed <n>] [--format text|json]
+    stamesp reach <rules> [<state>] [--length <n>]
+    stamesp test <rules>...
+    stamesp snapshot <directory> --stage <rules> [--stage <rules>]... [--bless]
+    stamesp coverage <rules> [--format text|json|annotated] [<input or directory>]...
+    stamesp profile <rules> [--format table|folded] [<input or directory>]...
+
+The input is read from the standard input when no file is given.
+With a policy, every viable state is followed in parallel and the ambiguous spans are reported.
+Stages may be machines compiled to a .stmc file, which cannot be used with a policy.
+Rules may be written in the DSL, or as JSON (.json) or YAML (.yaml, .yml) documents.";
+
+fn load(path: &str) -> Result<Symbols, String> {
+
+    let symbols = Symbols::from_file(path, false).map_err(|e| format!("Cannot read the file '{}': {}", path, e))?;
+
+    if !symbols.errors().is_empty() {
+        let errors: String = symbols.errors().iter().map(|e| e.to_string()).collect();
+        return Err(errors.trim_end().to_string());
+    }
+
+    Ok(symbols)
+}
+
+fn read_input(path: Option<&String>) -> Result<String, String> {
+    match path {
+        Some(path) => fs::read_to_string(path).map_err(|e| format!("Cannot read the file '{}': {}", path, e)),
+        None => {
+            let mut input = String::new();
+            std::io::stdin().read_to_string(&mut input).map_err(|e| e.to_string())?;
+            Ok(input)
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+
+    let mut stages = Vec::new();
+    let mut format = "text";
+    let mut policy = None;
+    let mut input = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--stage" => stages.push(iter.next().ok_or("Missing rule file after '--stage'")?),
+            "--format" => format = iter.next().ok_or("Missing format after '--format'")?,
+            "--policy" => policy = Some(match iter.next().map(String::as_str) {
+                Some("first") => Policy::FirstDeclared,
+                Some("longest") => Policy::LongestMatch,
+                Some("error") => Policy::Error,
+                _ => return Err(String::from("Expected 'first', 'longest' or 'error' after '--policy'"))
+            }),
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(format!("Unexpected argument '{}'", arg))
+        }
+    }
+
+    if stages.is_empty() {
+        return Err(String::from("At least one '--stage' is required"));
+    }
+
+    let compiled = |path: &&String| path.ends_with(".stmc");
+    if policy.is_some() && stages.iter().any(compiled) {
+        return Err(String::from("Compiled machines cannot be used with a policy"));
+    }
+
+    let symbols = stages.iter().filter(|x| !compiled(x)).map(|x| load(x)).collect::<Result<Vec<_>, _>>()?;
+    let files = stages.iter().filter(|x| compiled(x)).map(|x| fs::read(x).map_err(|e| format!("Cannot read the file '{}': {}", x, e))).collect::<Result<Vec<_>, _>>()?;
+
+    let input = read_input(input)?;
+    let nodes = match policy {
+        Some(policy) => {
+            let mut pipeline = Pipeline::new();
+            for sym in symbols.iter() {
+                pipeline = pipeline.stage(Interpreter::new(sym).map_err(|e| e.to_string())?);
+            }
+            let (nodes, ambiguities) = pipeline.simulate(&input, policy).map_err(|e| e.to_string())?;
+            for ambiguity in ambiguities.iter() {
+                eprintln!("{}", ambiguity);
+            }
+            nodes
+        },
+        // Compiled machines write the same nodes, faster
+        None => {
+            // Compiled machines are read in place from the bytes of their files
+            let (mut symbols, mut files) = (symbols.iter(), files.iter());
+            let mut machines = stages.iter().map(|x| if compiled(x) {
+                CompiledMachine::from_bytes(files.next().unwrap()).map_err(|e| format!("Cannot load the machine '{}': {}", x, e))
+            } else {
+                Interpreter::new(symbols.next().unwrap()).map(|x| x.compile()).map_err(|e| e.to_string())
+            });
+            let first = machines.next().unwrap()?;
+            let mut nodes = first.run(input.as_str()).map_err(|e| e.to_string())?;
+            for machine in machines {
+                nodes = machine?.run(nodes.as_slice()).map_err(|e| e.to_string())?;
+            }
+            nodes
+        }
+    };
+
+    match format {
+        "text" => print!("{}", export::to_text(&nodes)),
+        "xml" => print!("{}", export::to_xml(&nodes)),
+        "json" => println!("{}", export::to_json(&nodes)),
+        _ => return Err(format!("Unknown format '{}'", format))
+    }
+
+    Ok(())
+}
+
+// Saves the compiled machine next to the rule file, or to the given file
+fn compile(args: &[String]) -> Result<(), String> {
+
+    let mut rules = None;
+    let mut output = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--output" => output = Some(iter.next().ok_or("Missing file after '--output'")?.clone()),
+            _ if rules.is_none() => rules = Some(arg),
+            _ => return Err(format!("Unexpected argument '{}'", arg))
+        }
+    }
+
+    let rules = rules.ok_or("Missing rule file")?;
+    let symbols = load(rules)?;
+    let machine = Interpreter::new(&symbols).map_err(|e| e.to_string())?.compile();
+
+    let output = output.unwrap_or_else(|| Path::new(rules).with_extension("stmc").to_string_lossy().into_owned());
+    machine.to_file(&output).map_err(|e| format!("Cannot write the file '{}': {}", output, e))
+}
+
+// Prints the machine as a JSON or YAML document
+fn convert(args: &[String]) -> Result<(), String> {
+
+    let mut rules = None;
+    let mut format = "json";
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--format" => format = iter.next().ok_or("Missing format after '--format'")?,
+            _ if rules.is_none() => rules = Some(arg),
+            _ => return Err(format!("Unexpected argument '{}'", arg))
+        }
+    }
+
+    let symbols = load(rules.ok_or("Missing rule file")?)?;
+
+    match format {
+        "json" => println!("{}", symbols.to_json()),
+        #[cfg(feature = "yaml")]
+        "yaml" => print!("{}", symbols.to_yaml()),
+        #[cfg(not(feature = "yaml"))]
+        "yaml" => return Err(String::from("YAML needs stamesp to be built with the 'yaml' feature")),
+        _ => return Err(format!("Unknown format '{}'", format))
+    }
+
+    Ok(())
+}
+
+// Reports the states that could be merged or removed
+fn lint(args: &[String]) -> Result<(), String> {
+
+    let [path] = args else {
+        return Err(String::from(USAGE));
+    };
+
+    let (_, report) = load(path)?.minimize().map_err(|e| e.to_string())?;
+    print!("{}", report);
+
+    Ok(())
+}
+
+// Looks for the shortest input on which the machines do not write the same nodes
+fn diff(args: &[String]) -> Result<(), String> {
+
+    let mut files = Vec::new();
+    let mut length = 64;
+    let mut symbols = false;
+    let mut format = "text";
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--length" => length = number(iter.next(), arg)?,
+            "--symbols" => symbols = true,
+            "--format" => format = iter.next().ok_or("Missing format after '--format'")?,
+            _ => files.push(arg)
+        }
+    }
+
+    let [old, new] = files[..] else {
+        return Err(String::from(USAGE));
+    };
+
+    let (old, new) = (load(old)?, load(new)?);
+
+    // What changed in the tables, rather than in what the machines write
+    if symbols {
+        let changes = old.diff(&new);
+        match format {
+            "text" => changes.iter().for_each(|x| print!("{}", x)),
+            "json" => println!("{}", serde_json::to_string_pretty(&changes).unwrap()),
+            _ => return Err(format!("Unknown format '{}'", format))
+        }
+        return Ok(());
+    }
+
+    let old = Interpreter::new(&old).map_err(|e| e.to_string())?;
+    let new = Interpreter::new(&new).map_err(|e| e.to_string())?;
+
+    let verdict = old.equivalent(&new, length).map_err(|e| e.to_string())?;
+    print!("{}", verdict);
+
+    match verdict {
+        Verdict::Different(_) => Err(String::new()),
+        _ => Ok(())
+    }
+}
+
+fn number<T: std::str::FromStr>(value: Option<&String>, option: &str) -> Result<T, String> {
+    value.and_then(|x| x.parse().ok()).ok_or(format!("Expected a number after '{}'", option))
+}
+
+// Lists the accepted inputs with their nodes, or some of them picked at random
+fn enumerate(args: &[String]) -> Result<(), String> {
+
+    let mut path = None;
+    let mut length = 8;
+    let mut sample = None;
+    let mut seed = 0;
+    let mut format = "text";
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--length" => length = number(iter.next(), arg)?,
+            "--sample" => sample = Some(number(iter.next(), arg)?),
+            "--seed" => seed = number(iter.next(), arg)?,
+            "--format" => format = iter.next().ok_or("Missing format after '--format'")?,
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(format!("Unexpected argument '{}'", arg))
+        }
+    }
+
+    let symbols = load(path.ok_or(USAGE)?)?;
+    let interpreter = Interpreter::new(&symbols).map_err(|e| e.to_string())?;
+
+    let inputs = match sample {
+        Some(count) => interpreter.sample(count, length, seed),
+        None => interpreter.enumerate(length)
+    }.map_err(|e| e.to_string())?;
+
+    match format {
+        "text" => for (input, nodes) in inputs.iter() {
+            println!("{:?}", input);
+            for line in export::to_text(nodes).lines() {
+                println!("    {}", line);
+            }
+        },
+        "json" => {
+            let inputs: Vec<_> = inputs.iter().map(|(input, nodes)| serde_json::json!({ "input": input, "nodes": nodes })).collect();
+            println!("{}", serde_json::to_string_pretty(&inputs).unwrap());
+        },
+        _ => return Err(format!("Unknown format '{}'", format))
+    }
+
+    Ok(())
+}
+
+// Shortest accepted input ending in the state; fails when there is none
+fn reach(args: &[String]) -> Result<(), String> {
+
+    let mut files = Vec::new();
+    let mut length = 64;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--length" => length = number(iter.next(), arg)?,
+            _ => files.push(arg)
+        }
+    }
+
+    let (path, state) = match files[..] {
+        [path] => (path, None),
+        [path, state] => (path, Some(state.as_str())),
+        _ => return Err(String::from(USAGE))
+    };
+
+    let symbols = load(path)?;
+    let reach = Interpreter::new(&symbols).map_err(|e| e.to_string())?.reach(state, length).map_err(|e| e.to_string())?;
+    print!("{}", reach);
+
+    match reach {
+        Reach::Input(_) => Ok(()),
+        _ => Err(String::new())
+    }
+}
+
+// Runs the tests written in the rule files; fails when one of them does
+fn test(args: &[String]) -> Result<(), String> {
+
+    if args.is_empty() {
+        return Err(String::from(USAGE));
+    }
+
+    let mut failed = false;
+    for path in args.iter() {
+        let report = load(path)?.run_tests().map_err(|e| format!("{}: {}", path, e))?;
+        report.failures.iter().for_each(|x| print!("{}", x));
+        println!("{}: {} passed, {} failed", path, report.passed, report.failures.len());
+        failed |= !report.failures.is_empty();
+    }
+
+    if failed { Err(String::new()) } else { Ok(()) }
+}
+
+// Compares the output for each file of the directory with the expected one stored next to it
+fn snapshot(args: &[String]) -> Result<(), String> {
+
+    let mut stages = Vec::new();
+    let mut dir = None;
+    let mut bless = false;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--stage" => stages.push(iter.next().ok_or("Missing rule file after '--stage'")?),
+            "--bless" => bless = true,
+            _ if dir.is_none() => dir = Some(arg),
+            _ => return Err(format!("Unexpected argument '{}'", arg))
+        }
+    }
+
+    let (Some(dir), false) = (dir, stages.is_empty()) else {
+        return Err(String::from(USAGE));
+    };
+
+    let symbols = stages.iter().map(|x| load(x)).collect::<Result<Vec<_>, _>>()?;
+
+    let mut pipeline = Pipeline::new();
+    for sym in symbols.iter() {
+        pipeline = pipeline.stage(Interpreter::new(sym).map_err(|e| e.to_string())?);
+    }
+
+    let snapshots = testing::snapshots(&pipeline, Path::new(dir), bless).map_err(|e| format!("Cannot read the snapshots in '{}': {}", dir, e))?;
+
+    let mut failed = false;
+    for snapshot in snapshots.iter() {
+        print!("{}", snapshot);
+        failed |= matches!(snapshot.outcome, Outcome::Changed(_) | Outcome::Missing);
+    }
+
+    if failed { Err(String::new()) } else { Ok(()) }
+}
+
+// Contents of the files given, and of the files of the directories given, leaving out the expected outputs of the snapshots
+fn corpus(paths: &[&String]) -> Result<Vec<String>, String> {
+
+    let mut files = Vec::new();
+    for path in paths {
+        match fs::read_dir(path) {
+            Ok(entries) => {
+                let mut entries: Vec<_> = entries.filter_map(|x| x.ok().map(|x| x.path())).collect();
+                entries.retain(|x| x.is_file() && x.extension().is_none_or(|x| x != "expected"));
+                entries.sort();
+                files.extend(entries);
+            },
+            Err(_) => files.push(Path::new(path).to_path_buf())
+        }
+    }
+
+    files.iter().map(|x| fs::read_to_string(x).map_err(|e| format!("Cannot read the file '{}': {}", x.display(), e))).collect()
+}
+
+// Runs the tests of the rule file and the given inputs, then tells what the rules have that none of them went through
+fn coverage(args: &[String]) -> Result<(), String> {
+
+    let mut path = None;
+    let mut inputs = Vec::new();
+    let mut format = "text";
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--format" => format = iter.next().ok_or("Missing format after '--format'")?,
+            _ if path.is_none() => path = Some(arg),
+            _ => inputs.push(arg)
+        }
+    }
+
+    let path = path.ok_or(USAGE)?;
+    let symbols = load(path)?;
+    let interpreter = Interpreter::new(&symbols).map_err(|e| e.to_string())?;
+
+    // Inputs the machine fails on still cover what it went through before failing
+    let mut coverage = Coverage::new();
+    for test in symbols.test_inputs() {
+        let _ = interpreter.run_with(test, &mut coverage);
+    }
+    for input in corpus(&inputs)? {
+        let _ = interpreter.run_with(input.as_str(), &mut coverage);
+    }
+
+    match format {
+        "text" => print!("{}", coverage.report(&symbols)),
+        "json" => println!("{}", serde_json::to_string_pretty(&coverage.report(&symbols)).unwrap()),
+        "annotated" => {
+            let source = fs::read_to_string(path).map_err(|e| format!("Cannot read the file '{}': {}", path, e))?;
+            print!("{}", coverage.annotate(&symbols, path, &source));
+        },
+        _ => return Err(format!("Unknown format '{}'", format))
+    }
+
+    Ok(())
+}
+
+// Counts and times what the machine does on the inputs, read from the standard input when none is given
+fn profile(args: &[String]) -> Result<(), String> {
+
+    let mut path = None;
+    let mut inputs = Vec::new();
+    let mut format = "table";
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--format" => format = iter.next().ok_or("Missing format after '--format'")?,
+            _ if path.is_none() => path = Some(arg),
+            _ => inputs.push(arg)
+        }
+    }
+
+    let symbols = load(path.ok_or(USAGE)?)?;
+    let interpreter = Interpreter::new(&symbols).map_err(|e| e.to_string())?;
+
+    let inputs = if inputs.is_empty() { vec![read_input(None)?] } else { corpus(&inputs)? };
+
+    let mut profile = Profile::new();
+    for input in inputs.iter() {
+        if let Err(e) = interpreter.run_with(input.as_str(), &mut profile) {
+            eprintln!("{}", e.to_string().trim_end());
+        }
+    }
+
+    match format {
+        "table" => print!("{}", profile),
+        "folded" => print!("{}", profile.folded()),
+        _ => return Err(format!("Unknown format '{}'", format))
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let result = match args.first().map(String::as_str) {
+        Some("run") => run(&args[1..]),
+        Some("compile") => compile(&args[1..]),
+        Some("convert") => convert(&args[1..]),
+        Some("lint") => lint(&args[1..]),
+        Some("diff") => diff(&args[1..]),
+        Some("enumerate") => enumerate(&args[1..]),
+        Some("reach") => reach(&args[1..]),
+        Some("test") => test(&args[1..]),
+        Some("snapshot") => snapshot(&args[1..]),
+        Some("coverage") => coverage(&args[1..]),
+        Some("profile") => profile(&args[1..]),
+        _ => Err(String::from(USAGE))
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            if !e.is_empty() {
+                eprintln!("{}", e.trim_end());
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
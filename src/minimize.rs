@@ -0,0 +1,268 @@
+use std::{collections::HashMap, error::Error, fmt::Display};
+
+use super::determinize::Symbol;
+use super::interpreter::Interpreter;
+use super::parser::*;
+use super::scanner::Pos;
+
+// States of the original machine that were merged, the first of each group being the one kept
+#[derive(Debug, Default)]
+pub struct Minimization {
+    pub merged: Vec<Vec<(String, Pos)>>,
+    pub unreachable: Vec<(String, Pos)>,
+    pub equivalent: Vec<Vec<(String, Pos)>> // Groups that could be merged if their nodes had the same name
+}
+
+impl Display for Minimization {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+
+        for group in self.merged.iter() {
+            for (id, loc) in group.iter().skip(1) {
+                writeln!(f, "{}'{}' was merged into '{}'", loc, id, group[0].0)?;
+            }
+        }
+        for (id, loc) in self.unreachable.iter() {
+            writeln!(f, "{}'{}' is unreachable", loc, id)?;
+        }
+        for group in self.equivalent.iter() {
+            for (id, loc) in group.iter().skip(1) {
+                writeln!(f, "{}'{}' behaves as '{}' apart from the name of its nodes", loc, id, group[0].0)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Splits the blocks until every letter leads the states of a block to the same block
+// The moves of each letter are given for every state, followed by a sink for missing transitions and the state popped back to
+fn refine(initial: Vec<Vec<usize>>, moves: &[Vec<usize>]) -> Vec<Vec<usize>> {
+
+    let mut blocks = initial;
+    let mut waiting = blocks.clone();
+
+    while let Some(splitter) = waiting.pop() {
+
+        let mut inside = vec![false; moves.first().map_or(0, |x| x.len())];
+        for &s in splitter.iter() {
+            inside[s] = true;
+        }
+
+        for letter in moves.iter() {
+            for i in 0..blocks.len() {
+
+                let (into, out): (Vec<usize>, Vec<usize>) = blocks[i].iter().partition(|&&s| inside[letter[s]]);
+                if into.is_empty() || out.is_empty() {
+                    continue;
+                }
+
+                match waiting.iter().position(|x| *x == blocks[i]) {
+                    Some(w) => {
+                        waiting[w] = into.clone();
+                        waiting.push(out.clone());
+                    },
+                    None if into.len() <= out.len() => waiting.push(into.clone()),
+                    None => waiting.push(out.clone())
+                }
+
+                blocks[i] = into;
+                blocks.push(out);
+            }
+        }
+    }
+
+    blocks
+}
+
+// Groups states sharing a key, in the order they come
+fn group_by(states: &[&Object], key: impl Fn(&Object) -> String) -> Vec<Vec<usize>> {
+
+    let mut keys: Vec<String> = Vec::new();
+    let mut blocks: Vec<Vec<usize>> = Vec::new();
+
+    for (s, obj) in states.iter().enumerate() {
+        let k = key(obj);
+        match keys.iter().position(|x| *x == k) {
+            Some(i) => blocks[i].push(s),
+            None => {
+                keys.push(k);
+                blocks.push(vec![s]);
+            }
+        }
+    }
+
+    // The sink and the state popped back to stand apart
+    blocks.push(vec![states.len()]);
+    blocks.push(vec![states.len() + 1]);
+    blocks
+}
+
+impl Symbols {
+
+    // Merges the states that read and write the same way, with Hopcroft's partition refinement
+    // Two states are only merged when they open nodes with the same name and attributes; the others are reported as equivalent
+    pub fn minimize(&self) -> Result<(Symbols, Minimization), Box<dyn Error>> {
+
+        for obj in self.objects.values() {
+            if obj.call.is_some() {
+                return Err(Box::new(ParserError::NotMinimizable(obj.loc.clone(), format!("call in '{}'", obj.id))));
+            }
+            for tr in obj.transitions.iter() {
+                if !tr.conditions.is_empty() {
+                    return Err(Box::new(ParserError::NotMinimizable(tr.loc.clone(), format!("lookahead on '{}'", tr.origin))));
+                }
+            }
+        }
+
+        let entry = Interpreter::new(self)?.entry;
+
+        let mut reachable = vec![entry.id.as_str()];
+        let mut i = 0;
+        while i < reachable.len() {
+            for tr in self.objects[reachable[i]].transitions.iter() {
+                if tr.target != "." && !reachable.contains(&tr.target.as_str()) {
+                    reachable.push(&tr.target);
+                }
+            }
+            i += 1;
+        }
+
+        // States are taken in the order they were written, so that the first one of a group is kept
+        let mut states: Vec<&Object> = reachable.iter().map(|x| &self.objects[*x]).collect();
+        states.sort_by_key(|x| (x.loc.line, x.loc.column, x.id.clone()));
+        let index: HashMap<&str, usize> = states.iter().enumerate().map(|(i, x)| (x.id.as_str(), i)).collect();
+
+        let mut alphabet = vec![Symbol::Pseudo("^"), Symbol::Pseudo("$"), Symbol::Pseudo("@")];
+        for tr in states.iter().flat_map(|x| x.transitions.iter()) {
+            if let Some(set) = self.objects.get(&tr.origin) {
+                let litterals = set.values.iter().map(|&i| Symbol::Litteral(self.values[i].0.clone()));
+                let labels = set.labels.iter().map(|x| Symbol::Label(x.clone()));
+                for symbol in litterals.chain(labels) {
+                    if !alphabet.contains(&symbol) {
+                        alphabet.push(symbol);
+                    }
+                }
+            }
+        }
+
+        // A letter is a symbol together with what is written when reading it
+        // Popping back to '.' leads to the state found on the stack, which is the same for every state of a level
+        let sink = states.len();
+        let back = sink + 1;
+        let mut letters: Vec<(usize, String)> = Vec::new();
+        let mut moves: Vec<Vec<usize>> = Vec::new();
+
+        for (s, obj) in states.iter().enumerate() {
+            for (a, symbol) in alphabet.iter().enumerate() {
+
+                let Some(tr) = self.matching(obj, symbol).into_iter().next() else {
+                    continue;
+                };
+
+                // Staying in the state and keeping the node open while changing state write the same way
+                let keeps = (tr.target == "." || tr.keep_node) && tr.stack == StackBehaviour::Keep && tr.behaviour != WritingBehaviour::WriteBefore;
+                let output = format!("{:?} {:?} {:?} {:?} {}", tr.behaviour, tr.stack, tr.rewrite, tr.attributes, keeps);
+                let target = match tr.target.as_str() {
+                    "." if tr.stack == StackBehaviour::Pop => back,
+                    "." => s,
+                    target => index[target]
+                };
+
+                let letter = match letters.iter().position(|x| x.0 == a && x.1 == output) {
+                    Some(l) => l,
+                    None => {
+                        letters.push((a, output));
+                        moves.push(vec![sink; sink + 2]);
+                        moves.len() - 1
+                    }
+                };
+                moves[letter][s] = target;
+            }
+        }
+
+        let kind = |x: &Object| format!("{:?} {:?}", x.t & StateTypes::EndPoint, x.attributes);
+        let merged = refine(group_by(&states, |x| format!("{} {}", x.label(), kind(x))), &moves);
+        let equivalent = refine(group_by(&states, kind), &moves);
+
+        let named = |blocks: &Vec<Vec<usize>>| -> Vec<Vec<(String, Pos)>> {
+            let mut groups: Vec<Vec<(String, Pos)>> = blocks.iter()
+                .filter(|x| x.len() > 1)
+                .map(|x| {
+                    let mut x = x.clone();
+                    x.sort();
+                    x.iter().map(|&s| (states[s].id.clone(), states[s].loc.clone())).collect()
+                })
+                .collect();
+            groups.sort_by_key(|x| (x[0].1.line, x[0].1.column));
+            groups
+        };
+
+        let mut report = Minimization {
+            merged: named(&merged),
+            unreachable: Vec::new(),
+            equivalent: named(&equivalent)
+        };
+
+        // Equivalent states that are merged anyway are not worth a warning
+        report.equivalent.retain(|group| !report.merged.iter().any(|x| group.iter().all(|(id, _)| x.iter().any(|y| y.0 == *id))));
+
+        let mut kept: HashMap<&str, &str> = HashMap::new();
+        for group in report.merged.iter() {
+            for (id, _) in group.iter() {
+                kept.insert(&self.objects[id].id, &self.objects[&group[0].0].id);
+            }
+        }
+
+        let mut out = Symbols {
+            objects: HashMap::new(),
+            values: self.values.clone(),
+            errors: Vec::new(),
+            machines: HashMap::new(),
+            tests: self.tests.clone()
+        };
+
+        let targets: Vec<&String> = self.objects.values().flat_map(|x| x.transitions.iter().map(|tr| &tr.target)).collect();
+
+        for obj in self.objects.values() {
+
+            let state = !obj.transitions.is_empty() || obj.t != StateTypes::Through || targets.contains(&&obj.id);
+            let mut obj = obj.clone();
+
+            if !index.contains_key(obj.id.as_str()) || kept.get(obj.id.as_str()).is_some_and(|x| *x != obj.id) {
+                if !index.contains_key(obj.id.as_str()) && state {
+                    report.unreachable.push((obj.id.clone(), obj.loc.clone()));
+                }
+
+                // What was a state may still be used as a set
+                if obj.values.is_empty() && obj.labels.is_empty() {
+                    continue;
+                }
+                obj.transitions.clear();
+                obj.t = StateTypes::Through;
+                obj.attributes.clear();
+                obj.origins.clear();
+            }
+            else if let Some(group) = report.merged.iter().find(|x| x[0].0 == obj.id) {
+                obj.origins = group.iter().flat_map(|(id, loc)| {
+                    let member = &self.objects[id];
+                    if member.origins.is_empty() { vec![(id.clone(), loc.clone())] } else { member.origins.clone() }
+                }).collect();
+                if group.iter().any(|(id, _)| (self.objects[id].t & StateTypes::EntryPoint) != StateTypes::Through) {
+                    obj.t = obj.t | StateTypes::EntryPoint;
+                }
+            }
+
+            for tr in obj.transitions.iter_mut() {
+                if let Some(target) = kept.get(tr.target.as_str()) {
+                    tr.target = target.to_string();
+                }
+            }
+
+            out.objects.insert(obj.id.clone(), obj);
+        }
+
+        report.unreachable.sort_by_key(|x| (x.1.line, x.1.column));
+
+        Ok((out, report))
+    }
+}
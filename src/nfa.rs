@@ -0,0 +1,232 @@
+use std::{collections::{BTreeMap, HashMap}, fmt::Display};
+
+use serde::{Serialize, Deserialize};
+
+use super::interpreter::*;
+use super::parser::*;
+
+// How to choose between the runs that are still viable
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    FirstDeclared,
+    LongestMatch,
+    Error
+}
+
+// Part of the input that the machine can write in more than one way
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Ambiguity {
+    pub start: usize,
+    pub end: usize,
+    pub state: String
+}
+
+impl Display for Ambiguity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "input:{:0>3}-{:0>3} --> Ambiguous tokenization, reaching state '{}'", self.start, self.end, self.state)
+    }
+}
+
+struct Thread<'a> {
+    run: Run<'a>,
+    // Choices made so far, compared in order to rank threads
+    key: Vec<(usize, usize)>
+}
+
+impl Thread<'_> {
+
+    fn output(&self) -> Vec<Node> {
+        let mut out = self.run.nodes.clone();
+        if !self.run.node.is_empty() {
+            out.push(self.run.node.clone());
+        }
+        out
+    }
+
+    // Threads in the same state, with the same stack, behave the same way from now on
+    fn signature(&self) -> (String, Vec<String>) {
+        (self.run.current.id.clone(), self.run.frames.iter().map(|x| x.origin.id.clone()).collect())
+    }
+}
+
+// Keeps the error met furthest in the input; at the same position, the one of the thread ranked first, as threads are tried in order
+fn fail(error: &mut Option<(usize, InterpreterError)>, pos: usize, e: InterpreterError) {
+    if error.as_ref().is_none_or(|(at, _)| pos > *at) {
+        *error = Some((pos, e));
+    }
+}
+
+// Offset of the first node on which two outputs differ
+fn divergence(a: &[Node], b: &[Node]) -> Option<usize> {
+    for (x, y) in a.iter().zip(b.iter()) {
+        if x != y {
+            return Some(x.start.min(y.start));
+        }
+    }
+    match a.len().cmp(&b.len()) {
+        std::cmp::Ordering::Less => Some(b[a.len()].start),
+        std::cmp::Ordering::Greater => Some(a[b.len()].start),
+        std::cmp::Ordering::Equal => None
+    }
+}
+
+impl<'a> Interpreter<'a> {
+
+    // Every transition the state can take at the position, with its rank among the state's transitions
+    fn candidates<U: Units + ?Sized>(&self, current: &'a Object, input: &U, pos: usize) -> Vec<(usize, &'a Transition, usize)> {
+
+        let mut out = Vec::new();
+
+        for (i, tr) in current.transitions.iter().enumerate().filter(|(_, x)| !PSEUDO_SETS.contains(&x.origin.as_str())) {
+            if let Some(len) = self.match_lengths(&tr.origin, input, pos).into_iter().find(|&len| self.check(&tr.conditions, input, pos + len)) {
+                out.push((i, tr, len));
+            }
+        }
+
+        // '@' only stands for tokens no transition on a set of the state accepts
+        if out.is_empty() {
+            if let Some(len) = input.unit(pos) {
+                for (i, tr) in current.transitions.iter().enumerate().filter(|(_, x)| x.origin == "@") {
+                    if self.check(&tr.conditions, input, pos + len) {
+                        out.push((i, tr, len));
+                    }
+                }
+            }
+        }
+
+        out
+    }
+
+    fn rank(policy: Policy, index: usize, len: usize) -> (usize, usize) {
+        match policy {
+            Policy::LongestMatch => (usize::MAX - len, index),
+            _ => (0, index)
+        }
+    }
+
+    // Follows every viable state in parallel instead of a single one, merging the runs that reach the same state
+    pub fn simulate<U: Units + ?Sized>(&self, input: &U, policy: Policy) -> Result<(Vec<Node>, Vec<Ambiguity>), InterpreterError> {
+
+        let mut queue: BTreeMap<usize, Vec<Thread<'a>>> = BTreeMap::new();
+        let mut finished: Vec<Thread<'a>> = Vec::new();
+        let mut ambiguities: Vec<Ambiguity> = Vec::new();
+        let mut error: Option<(usize, InterpreterError)> = None;
+
+        let run = Run {
+            current: self.entry,
+            node: Node::new(self.entry, input.start(0)),
+            nodes: Vec::new(),
+            frames: Vec::new()
+        };
+
+        let starts: Vec<(usize, &Transition)> = self.entry.transitions.iter().enumerate().filter(|(_, x)| x.origin == "^").collect();
+        if starts.is_empty() {
+            queue.entry(0).or_default().push(Thread { run: run.clone(), key: Vec::new() });
+        }
+        for (i, tr) in starts {
+            let mut run = run.clone();
+            match self.apply(tr, &mut run, input, 0, 0, &mut ()) {
+                Ok(pos) => queue.entry(pos).or_default().push(Thread { run, key: vec![Self::rank(policy, i, 0)] }),
+                Err(e) => fail(&mut error, 0, e)
+            }
+        }
+
+        while let Some((pos, mut threads)) = queue.pop_first() {
+
+            threads.sort_by(|a, b| a.key.cmp(&b.key));
+
+            let mut seen: HashMap<(String, Vec<String>), usize> = HashMap::new();
+            let mut kept: Vec<Thread<'a>> = Vec::new();
+
+            for thread in threads {
+                match seen.get(&thread.signature()) {
+                    Some(&i) => {
+                        if let Some(start) = divergence(&kept[i].output(), &thread.output()) {
+                            ambiguities.push(Ambiguity { start, end: input.end(pos), state: thread.run.current.id.clone() });
+                        }
+                    },
+                    None => {
+                        seen.insert(thread.signature(), kept.len());
+                        kept.push(thread);
+                    }
+                }
+            }
+
+            for thread in kept {
+
+                if pos == input.size() {
+                    let ends: Vec<(usize, &Transition)> = thread.run.current.transitions.iter().enumerate().filter(|(_, x)| x.origin == "$").collect();
+                    let mut runs = Vec::new();
+
+                    if ends.is_empty() {
+                        runs.push((thread.run.clone(), thread.key.clone()));
+                    }
+                    for (i, tr) in ends {
+                        let mut run = thread.run.clone();
+                        let mut key = thread.key.clone();
+                        key.push(Self::rank(policy, i, 0));
+                        match self.apply(tr, &mut run, input, pos, 0, &mut ()) {
+                            Ok(_) => runs.push((run, key)),
+                            Err(e) => fail(&mut error, pos, e)
+                        }
+                    }
+
+                    for (mut run, key) in runs {
+                        run.flush();
+                        run.node = Node::new(run.current, input.end(pos));
+                        while run.close(input.end(pos)).is_some() {}
+
+                        if (run.current.t & StateTypes::EndPoint) == StateTypes::Through {
+                            fail(&mut error, pos, InterpreterError::NotAnEndPoint(input.end(pos), run.current.id.clone()));
+                        }
+                        else {
+                            finished.push(Thread { run, key });
+                        }
+                    }
+                    continue;
+                }
+
+                let candidates = self.candidates(thread.run.current, input, pos);
+                if candidates.is_empty() {
+                    let unit = input.text(pos, input.unit(pos).unwrap_or(0)).to_string();
+                    fail(&mut error, pos, InterpreterError::NoTransition(input.start(pos), thread.run.current.id.clone(), unit));
+                }
+
+                for (i, tr, len) in candidates {
+                    let mut run = thread.run.clone();
+                    let mut key = thread.key.clone();
+                    key.push(Self::rank(policy, i, len));
+
+                    match self.apply(tr, &mut run, input, pos, len, &mut ()) {
+                        Ok(next) => queue.entry(next).or_default().push(Thread { run, key }),
+                        Err(e) => fail(&mut error, pos, e)
+                    }
+                }
+            }
+        }
+
+        finished.sort_by(|a, b| a.key.cmp(&b.key));
+
+        let Some(best) = finished.first() else {
+            return Err(error.map_or(InterpreterError::NoRunFinished(input.end(input.size())), |(_, e)| e));
+        };
+
+        let output = best.output();
+        for other in finished.iter().skip(1) {
+            if let Some(start) = divergence(&output, &other.output()) {
+                ambiguities.push(Ambiguity { start, end: input.end(input.size()), state: other.run.current.id.clone() });
+            }
+        }
+
+        ambiguities.sort_by_key(|x| (x.start, x.end));
+        ambiguities.dedup();
+
+        if policy == Policy::Error {
+            if let Some(first) = ambiguities.first() {
+                return Err(InterpreterError::Ambiguous(first.start, first.end));
+            }
+        }
+
+        Ok((output, ambiguities))
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashSet;
+
+use super::compile::*;
+#[cfg(feature = "rayon")]
+use super::interpreter::{InterpreterError, Node};
+use super::parser::*;
+
+impl CompiledMachine<'_> {
+
+    // States the entry state leads to, when none of them pushes, pops or calls a machine
+    fn flat_states(&self) -> Option<Vec<StateId>> {
+
+        let mut seen = vec![self.entry];
+        let mut i = 0;
+
+        while let Some(&id) = seen.get(i) {
+            let state = &self.states[id as usize];
+            if state.call.is_some() || state.transitions.iter().any(|tr| tr.stack != StackBehaviour::Keep) {
+                return None;
+            }
+            for target in state.transitions.iter().filter_map(|tr| tr.target) {
+                if !seen.contains(&target) {
+                    seen.push(target);
+                }
+            }
+            i += 1;
+        }
+
+        Some(seen)
+    }
+
+    fn chars(&self, set: SetId) -> Vec<char> {
+        let set = &self.sets[set as usize];
+        let ascii = (0..128u8).filter(|&x| set.ascii & (1 << x) != 0).map(char::from);
+        ascii.chain(set.ranges.iter().flat_map(|&(start, end)| start..=end)).collect()
+    }
+
+    // Sets after which the machine is always in its entry state, with an empty node, whatever it read before:
+    // every state takes the same transition on them, back to the entry state without writing them,
+    // and no other set read by the states has any of their characters on its own
+    pub(crate) fn split_sets(&self) -> Vec<SetId> {
+
+        let Some(states) = self.flat_states() else { return Vec::new() };
+
+        let read: HashSet<SetId> = states.iter()
+            .flat_map(|&x| self.states[x as usize].transitions.iter())
+            .filter_map(|tr| if let Origin::Set(id) = tr.origin { Some(id) } else { None })
+            .collect();
+
+        // Transitions keeping the node don't flush it, whatever state they lead to
+        let kept = |tr: &CompiledTransition| (tr.target.is_none() || tr.keep_node) && tr.behaviour != WritingBehaviour::WriteBefore;
+
+        // The node of the entry state stays empty when nothing is written to it after entering it
+        let clean = states.iter().all(|&x| self.states[x as usize].transitions.iter().all(|tr| {
+            tr.target.unwrap_or(x) != self.entry || (tr.behaviour != WritingBehaviour::WriteAfter && (x == self.entry || !kept(tr)))
+        }));
+
+        let mut out: Vec<SetId> = read.iter().copied().filter(|&id| {
+            let set = &self.sets[id as usize];
+            if !set.multi.is_empty() || set.empty || !set.labels.is_empty() {
+                return false;
+            }
+
+            let chars = self.chars(id);
+            if read.iter().any(|&other| other != id && chars.iter().any(|&c| self.sets[other as usize].contains(c))) {
+                return false;
+            }
+
+            states.iter().all(|&x| {
+                let Some(tr) = self.states[x as usize].transitions.iter().find(|tr| tr.origin == Origin::Set(id)) else { return false };
+                tr.conditions.is_empty() && tr.target.unwrap_or(x) == self.entry && tr.behaviour != WritingBehaviour::WriteAfter
+                    && (!kept(tr) || (x == self.entry && clean))
+            })
+        }).collect();
+
+        out.sort();
+        out
+    }
+
+    // Names of the sets the input may be split after
+    pub fn split_set_names(&self) -> Vec<&str> {
+        self.split_sets().into_iter().filter_map(|x| self.set_name(x)).collect()
+    }
+
+    // Offsets where the input may be cut, each one at least `size` bytes after the previous one
+    // A character of a split set is only a safe cut when no longer litteral read by the machine matches across it
+    pub fn split_points(&self, input: &str, size: usize) -> Vec<usize> {
+
+        let sets = self.split_sets();
+        if sets.is_empty() {
+            return Vec::new();
+        }
+
+        let longer: Vec<&str> = self.flat_states().unwrap_or_default().iter()
+            .flat_map(|&x| self.states[x as usize].transitions.iter())
+            .filter_map(|tr| if let Origin::Set(id) = tr.origin { Some(&self.sets[id as usize].multi) } else { None })
+            .flat_map(Litterals::iter)
+            .collect();
+
+        let across = |at: usize, c: char| longer.iter().any(|x| {
+            x.match_indices(c).any(|(i, _)| at >= i && input.get(at - i..).is_some_and(|rest| rest.starts_with(x)))
+        });
+
+        let mut out = Vec::new();
+        let mut next = size.max(1);
+
+        while next < input.len() {
+            while !input.is_char_boundary(next) {
+                next += 1;
+            }
+            let found = input[next..].char_indices()
+                .map(|(i, c)| (next + i, c))
+                .find(|&(at, c)| sets.iter().any(|&x| self.sets[x as usize].contains(c)) && !across(at, c));
+
+            match found {
+                Some((at, c)) if at + c.len_utf8() < input.len() => {
+                    out.push(at + c.len_utf8());
+                    next = at + c.len_utf8() + size.max(1);
+                },
+                _ => break
+            }
+        }
+
+        out
+    }
+
+    // Same as run, the parts of the input between split points being read on all cores
+    #[cfg(feature = "rayon")]
+    pub fn run_parallel(&self, input: &str, size: usize) -> Result<Vec<Node>, InterpreterError> {
+        use rayon::prelude::*;
+
+        let points = self.split_points(input, size);
+        let starts = std::iter::once(0).chain(points.iter().copied());
+        let ends = points.iter().copied().chain(std::iter::once(input.len()));
+        let bounds: Vec<(usize, usize)> = starts.zip(ends).collect();
+
+        let chunks: Vec<Result<Vec<Node>, InterpreterError>> = bounds.into_par_iter().map(|(from, to)| self.chunk(input, from, to)).collect();
+
+        // The first error is the one a sequential run would have stopped at
+        let mut out = Vec::new();
+        for chunk in chunks {
+            out.extend(chunk?);
+        }
+        Ok(out)
+    }
+}
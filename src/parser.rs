@@ -1,6 +1,6 @@
 
 
-use std::{collections::HashMap, fmt::Display, thread::current};
+use std::{collections::HashMap, fmt::Display, fs, path::{Path, PathBuf}};
 
 use num_derive::FromPrimitive;
 use serde::{Serialize, Deserialize};
@@ -13,7 +13,15 @@ pub enum ParserError{
     DuplicateTransition(Pos, String),
     UndefinedIdentifier(Pos, String),
     Unclosed(Pos, String),
-    NotAttached(Pos, String)
+    NotAttached(Pos, String),
+    UnreadableFile(Pos, String),
+    RecursiveCall(Pos, String),
+    NotDeterminizable(Pos, String),
+    NotMinimizable(Pos, String),
+    NotExplorable(Pos, String),
+    Malformed(Pos, String),
+    InvalidAttribute(Pos, String),
+    StartAhead(Pos)
 }
 
 impl Error for ParserError {}
@@ -26,6 +34,14 @@ impl Display for ParserError {
             ParserError::UndefinedIdentifier(loc, id) => writeln!(f, "{}Undefined identifier: '{}'", loc, id),
             ParserError::Unclosed(loc, id) => writeln!(f, "{}Unclosed object: '{}'", loc, id),
             ParserError::NotAttached(loc, token) => writeln!(f, "{}Unattached object specifier: '{}'", loc, token),
+            ParserError::UnreadableFile(loc, file) => writeln!(f, "{}Cannot read the file: '{}'", loc, file),
+            ParserError::RecursiveCall(loc, file) => writeln!(f, "{}Recursive call to the file: '{}'", loc, file),
+            ParserError::NotDeterminizable(loc, what) => writeln!(f, "{}Cannot determinize the machine because of a {}", loc, what),
+            ParserError::NotMinimizable(loc, what) => writeln!(f, "{}Cannot minimize the machine because of a {}", loc, what),
+            ParserError::NotExplorable(loc, what) => writeln!(f, "{}Cannot explore the inputs of the machine because of a {}", loc, what),
+            ParserError::Malformed(loc, what) => writeln!(f, "{}Malformed document: {}", loc, what),
+            ParserError::InvalidAttribute(loc, key) => writeln!(f, "{}Invalid attribute name: '{}'", loc, key),
+            ParserError::StartAhead(loc) => writeln!(f, "{}Lookahead on '^', while the start of the input is never ahead", loc),
         }
         
     }
@@ -36,11 +52,17 @@ enum States {
     Void,
     Set,
     State,
-    Transition
+    Transition,
+    Lookahead,
+    Rewrite,
+    Attributes,
+    Call,
+    Test,
+    Expected
 }
 
 #[derive(Debug, Serialize, Deserialize, FromPrimitive, Clone, Copy, PartialEq)]
-enum StateTypes {
+pub(crate) enum StateTypes {
     Through = 0b00000000,
     EntryPoint = 0b00000001,
     EndPoint = 0b00000010,
@@ -96,58 +118,244 @@ impl std::ops::BitXor for StateTypes {
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
-enum WritingBehaviour {
+pub(crate) enum WritingBehaviour {
     WriteAfter,
     WriteBefore,
     NoWrite
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub(crate) enum StackBehaviour {
+    Keep,
+    Push,
+    Pop
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Condition {
+    pub(crate) negated: bool,
+    pub(crate) sets: Vec<String> // Sequence of sets the upcoming input must (not) match
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Transition {
+    pub(crate) loc: Pos,
+    pub(crate) origin: String,
+    pub(crate) target: String,
+    pub(crate) behaviour: WritingBehaviour,
+    pub(crate) stack: StackBehaviour,
+    pub(crate) conditions: Vec<Condition>,
+    pub(crate) rewrite: Option<String>, // Text written in place of the matched token
+    pub(crate) attributes: Vec<(String, String)>,
+    pub(crate) keep_node: bool // The current node stays open while changing state, as with '.'
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Object {
-    values: Vec<usize>,
-    id: String,
-    transitions: Vec<(Pos, String, String, WritingBehaviour)>, // Mapping values to objects
-    loc: Pos,
-    t: StateTypes
+    pub(crate) values: Vec<usize>,
+    pub(crate) labels: Vec<String>, // Labels of the nodes of a previous machine, when reading its output
+    pub(crate) id: String,
+    pub(crate) transitions: Vec<Transition>, // Mapping values to objects
+    pub(crate) loc: Pos,
+    pub(crate) t: StateTypes,
+    pub(crate) attributes: Vec<(String, String)>, // Given to every node opened in this state
+    pub(crate) call: Option<String>, // Machine the state delegates to, as a key of Symbols.machines
+    pub(crate) origins: Vec<(String, Pos)>, // States written by the author that were merged into this one
+    pub(crate) label: Option<String> // Name of the nodes given by a document, when it is not the id
+}
+
+// Attributes are written as XML attributes, next to the ones every node has
+pub(crate) fn is_attribute_name(key: &str) -> bool {
+    key.chars().next().is_some_and(char::is_alphabetic) && key.chars().all(char::is_alphanumeric) && !["id", "start", "end"].contains(&key)
+}
+
+impl Object {
+
+    // Name given to the nodes opened in this state
+    pub(crate) fn label(&self) -> &str {
+        self.label.as_deref().or(self.origins.first().map(|x| x.0.as_str())).unwrap_or(&self.id)
+    }
+}
+
+// Example written next to the rules, with the text of the nodes the machine should write
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Test {
+    pub(crate) loc: Pos,
+    pub(crate) input: String,
+    pub(crate) expected: Vec<String>
 }
 
 #[derive(Debug)]
 pub struct Symbols {
     pub objects: HashMap<String, Object>,
-    values: Vec<(String, Pos)>,
-    errors: Vec<Box<dyn Error>>
+    pub(crate) values: Vec<(String, Pos)>,
+    pub(crate) errors: Vec<Box<dyn Error>>,
+    pub(crate) machines: HashMap<String, Symbols>,
+    pub(crate) tests: Vec<Test>
 }
 
-// We should use a hashmap in the making, but the symbol table should only use indices, and abstract away the names of the states (mostly)
+// A hashmap is used in the making; Interpreter::compile() then only uses indices, and keeps the names of the states for diagnostics
 
 
 impl Symbols {
 
     pub fn parser(scan: Scanner, verbose: bool) -> Self {
+        Self::parser_from(scan, verbose, &mut Vec::new())
+    }
+
+    // Rule files, or documents in the interchange format when their extension says so
+    pub fn from_file(path: &str, verbose: bool) -> std::io::Result<Self> {
+        let input = fs::read_to_string(path)?;
+        Ok(Self::read(&input, path, verbose, &mut Vec::new()))
+    }
 
-        let mut symbols = Self {
+    fn read(input: &str, path: &str, verbose: bool, callers: &mut Vec<PathBuf>) -> Self {
+        match Path::new(path).extension().and_then(|x| x.to_str()) {
+            Some("json") => Self::json_from(input, path, verbose, callers),
+            #[cfg(feature = "yaml")]
+            Some("yaml" | "yml") => Self::yaml_from(input, path, verbose, callers),
+            _ => Self::parser_from(Scanner::new(input, path), verbose, callers)
+        }
+    }
+
+    pub(crate) fn empty() -> Self {
+        Self {
             objects: HashMap::new(),
             values: Vec::new(),
             errors: Vec::new(),
-        };
+            machines: HashMap::new(),
+            tests: Vec::new()
+        }
+    }
+
+    pub fn errors(&self) -> &[Box<dyn Error>] {
+        &self.errors
+    }
+
+    pub fn test_inputs(&self) -> impl Iterator<Item = &str> {
+        self.tests.iter().map(|x| x.input.as_str())
+    }
+
+    // Parses the file called from a rule file, relative to the latter
+    pub(crate) fn load(&mut self, loc: &Pos, file: &str, verbose: bool, callers: &mut Vec<PathBuf>) -> Option<String> {
+
+        let current = PathBuf::from(&loc.file);
+        let path = current.parent().unwrap_or(Path::new("")).join(file);
+        let key = path.to_string_lossy().to_string();
+
+        let this = current.canonicalize().unwrap_or(current);
+        let canonical = path.canonicalize().unwrap_or(path);
+
+        if canonical == this || callers.contains(&canonical) {
+            self.errors.push(Box::new(ParserError::RecursiveCall(loc.clone(), key)));
+            return None;
+        }
+
+        if !self.machines.contains_key(&key) {
+            let Ok(input) = fs::read_to_string(&canonical) else {
+                self.errors.push(Box::new(ParserError::UnreadableFile(loc.clone(), key)));
+                return None;
+            };
+
+            callers.push(this);
+            let mut machine = Self::read(&input, &key, verbose, callers);
+            callers.pop();
+
+            self.errors.append(&mut machine.errors);
+            self.machines.insert(key.clone(), machine);
+        }
+
+        Some(key)
+    }
+
+    fn parser_from(scan: Scanner, verbose: bool, callers: &mut Vec<PathBuf>) -> Self {
+
+        let mut symbols = Self::empty();
 
 
         let mut state = vec![States::Void];
         let mut stack = Vec::<Token>::new();
         let mut writing_behaviour = WritingBehaviour::WriteAfter;
+        let mut stack_behaviour = StackBehaviour::Keep;
         let mut state_type = StateTypes::Through;
+        let mut condition: Option<Condition> = None;
+        let mut conditions = Vec::<Condition>::new();
+        let mut rewrite: Option<String> = None;
+        let mut attributes = Vec::<(String, String)>::new();
+        let mut call: Option<String> = None;
+        let mut alternatives = 0;
+        let mut test: Option<Test> = None;
+        let mut expects = false; // '=>' was read after the input of the test
     
         let mut iter = scan.peekable();
         for token in iter.by_ref() {
             
             if let Ok(tok) = token {
 
-                println!("{:?}", state.last());
-                println!("{tok}");
+                if verbose {
+                    println!("{:?}", state.last());
+                    println!("{tok}");
+                }
     
                 match &tok {
                     Token::Comment(_, _) => {},
-                    Token::OpenParen(loc) => {
+                    Token::Test(loc) => {
+                        if let (Some(States::Void), true) = (state.last(), stack.is_empty()) {
+                            test = Some(Test { loc: loc.clone(), input: String::new(), expected: Vec::new() });
+                            expects = false;
+                            state.push(States::Test);
+                        }
+                        else {
+                            symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from("'test'")))); 
+                        }
+                    },
+                    Token::Litteral(loc, value) if state.last() == Some(&States::Test) => {
+                        match test.as_mut() {
+                            Some(test) if !expects => test.input = value.clone(),
+                            _ => symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), value.clone())))
+                        }
+                    },
+                    Token::Litteral(_, value) if state.last() == Some(&States::Expected) => {
+                        if let Some(test) = test.as_mut() {
+                            test.expected.push(value.clone());
+                        }
+                    },
+                    Token::Implies(loc) if state.last() == Some(&States::Test) => {
+                        if expects {
+                            symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from("'=>'"))));
+                        }
+                        expects = true;
+                    },
+                    Token::Equal(loc) | Token::Greater(loc) if state.last() == Some(&States::Test) => {
+                        let specifier = if matches!(tok, Token::Equal(_)) { "'='" } else { "'>'" };
+                        symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from(specifier))));
+                    },
+                    Token::OpenSquare(loc) if state.last() == Some(&States::Test) => {
+                        if !expects {
+                            symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from("'['"))));
+                        }
+                        state.push(States::Expected);
+                    },
+                    Token::CloseSquare(_) if state.last() == Some(&States::Expected) => {
+                        state.pop();
+                    },
+                    // Commas only separate the expected texts of a test
+                    Token::Comma(_) if state.last() == Some(&States::Expected) => {},
+                    Token::Comma(loc) => {
+                        symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from("','"))));
+                    },
+                    Token::SemiColon(loc) if state.last() == Some(&States::Test) => {
+                        // The test is kept apart from the machine, which does not use it
+                        match test.take() {
+                            Some(test) if expects => symbols.tests.push(test),
+                            _ => symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from("'test'"))))
+                        }
+                        state.pop();
+                    },
+                    Token::OpenParen(_) if condition.is_some() => {
+                        state.push(States::Lookahead);
+                    },
+                    Token::OpenParen(_) => {
 
                         let top = stack.last();
                         match top {
@@ -157,8 +365,16 @@ impl Symbols {
                                         id: value.clone(), 
                                         loc: l.clone(), 
                                         values: Vec::new(), 
+                                        labels: Vec::new(), 
                                         transitions: Vec::new(), 
-                                        t: state_type});
+                                        t: state_type,
+                                        attributes: Vec::new(),
+                                        call: None,
+                                        origins: Vec::new(),
+                                        label: None});
+                                }
+                                if let Some(obj) = symbols.objects.get_mut(value) {
+                                    obj.attributes.append(&mut attributes);
                                 }
                                 state_type = StateTypes::Through;
                                 state.push(States::Set);
@@ -167,16 +383,20 @@ impl Symbols {
                             None => todo!(),
                         }
                     },
-                    Token::CloseParen(loc) => {
+                    Token::CloseParen(_) => {
                         if let Some(States::Set) = state.last() {
                             state.pop();
                             
                         }
+                        else if let (Some(States::Lookahead), Some(cond)) = (state.last(), condition.take()) {
+                            conditions.push(cond);
+                            state.pop();
+                        }
                         else {
                             todo!()
                         }
                     },
-                    Token::OpenBrackets(loc) => {
+                    Token::OpenBrackets(_) => {
                         let top = stack.last();
                         match top {
                             Some(Token::Identifier(l, value)) => {
@@ -185,8 +405,19 @@ impl Symbols {
                                         id: value.clone(), 
                                         loc: l.clone(), 
                                         values: Vec::new(), 
+                                        labels: Vec::new(), 
                                         transitions: Vec::new(), 
-                                        t: state_type});
+                                        t: state_type,
+                                        attributes: Vec::new(),
+                                        call: None,
+                                        origins: Vec::new(),
+                                        label: None});
+                                }
+                                if let Some(obj) = symbols.objects.get_mut(value) {
+                                    obj.attributes.append(&mut attributes);
+                                    if call.is_some() {
+                                        obj.call = call.take();
+                                    }
                                 }
                                 state_type = StateTypes::Through;
                                 state.push(States::State);
@@ -195,7 +426,7 @@ impl Symbols {
                             None => todo!(),
                         }
                     },
-                    Token::CloseBrackets(loc) => {
+                    Token::CloseBrackets(_) => {
                         if let Some(States::State) = state.last() {
                             state.pop();
                         }
@@ -203,8 +434,43 @@ impl Symbols {
                             todo!()
                         }
                     },
-                    Token::Identifier(loc, value) => {
-                        stack.push(tok);
+                    Token::Identifier(_, value) => {
+                        if let (Some(States::Lookahead), Some(cond)) = (state.last(), condition.as_mut()) {
+                            cond.sets.push(value.clone());
+                        }
+                        else if let (Some(States::Set), Some(Token::Identifier(_, id))) = (state.last(), stack.last()) {
+                            if let Some(obj) = symbols.objects.get_mut(id) {
+                                obj.labels.push(value.clone());
+                            }
+                        }
+                        else {
+                            stack.push(tok);
+                        }
+                    },
+                    Token::Litteral(_, value) if state.last() == Some(&States::Rewrite) => {
+                        rewrite = Some(value.clone());
+                        state.pop();
+                    },
+                    Token::Litteral(loc, value) if state.last() == Some(&States::Call) => {
+                        call = symbols.load(loc, value, verbose, callers);
+                        state.pop();
+                    },
+                    Token::Call(loc) => {
+                        if let (Some(States::Void), Some(Token::Identifier(_, _))) = (state.last(), stack.last()) {
+                            state.push(States::Call);
+                        }
+                        else {
+                            symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from("'call'")))); 
+                        }
+                    },
+                    Token::Litteral(loc, value) if state.last() == Some(&States::Attributes) => {
+                        match stack.pop() {
+                            Some(Token::Identifier(at, key)) if !is_attribute_name(&key) => {
+                                symbols.errors.push(Box::new(ParserError::InvalidAttribute(at, key)));
+                            },
+                            Some(Token::Identifier(_, key)) => attributes.push((key, value.clone())),
+                            _ => symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), value.clone())))
+                        }
                     },
                     Token::Litteral(loc, value) => {
                         if let Some(States::Set) = state.last() {
@@ -224,109 +490,215 @@ impl Symbols {
                             todo!()
                         }
                     },
-                    Token::Equal(loc) => {
-                        state.push(States::Transition);
+                    Token::Equal(_) => {
+                        if state.last() != Some(&States::Attributes) {
+                            state.push(States::Transition);
+                        }
+                    },
+                    // Outside of a test, '=>' is a transition pushing its target, as '= >'
+                    Token::Implies(loc) => {
+                        if state.last() != Some(&States::Attributes) {
+                            state.push(States::Transition);
+                        }
+                        if let (Some(States::Transition), StackBehaviour::Keep) = (state.last(), stack_behaviour) {
+                            stack_behaviour = StackBehaviour::Push;
+                        }
+                        else {
+                            symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from("'>'"))));
+                        }
+                    },
+                    Token::OpenSquare(loc) => {
+                        if let Some(Token::Identifier(_, _)) = stack.last() {
+                            state.push(States::Attributes);
+                        }
+                        else {
+                            symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from("'['")))); 
+                        }
+                    },
+                    Token::CloseSquare(_) => {
+                        if let Some(States::Attributes) = state.last() {
+                            state.pop();
+                        }
+                        else {
+                            todo!()
+                        }
+                    },
+                    Token::Arrow(loc) => {
+                        if let (Some(States::State), Some(Token::Identifier(_, _)), None) = (state.last(), stack.last(), &rewrite) {
+                            state.push(States::Rewrite);
+                        }
+                        else {
+                            symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from("'->'")))); 
+                        }
                     },
                     Token::Star(loc) => {
                         if let Some(Token::Identifier(_, _)) = stack.last() {
 
                             if (state_type & StateTypes::EntryPoint) != StateTypes::Through {
-                                symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), format!("'*'")))); 
+                                symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from("'*'")))); 
                             }
                             else {
                                 state_type = state_type | StateTypes::EntryPoint;
                             }
                         }
                         else {
-                            symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), format!("'*'")))); 
+                            symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from("'*'")))); 
                         }
                     },
                     Token::Colon(loc) => {
                         if let Some(Token::Identifier(_, _)) = stack.last() {
 
                             if (state_type & StateTypes::EndPoint) != StateTypes::Through {
-                                symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), format!("':'")))); 
+                                symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from("':'")))); 
                             }
                             else {
                                 state_type = state_type | StateTypes::EndPoint;
                             }
                         }
                         else {
-                            symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), format!("':'")))); 
+                            symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from("':'")))); 
                         }
                     },
                     Token::Hat(loc) => {
                         if let Some(States::Transition) = state.last() {
                             if writing_behaviour != WritingBehaviour::WriteAfter {
-                                symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), format!("'^'")))); 
+                                symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from("'^'")))); 
                             }
                             else {
                                 writing_behaviour = WritingBehaviour::NoWrite;
                             }
                         }
+                        else if let Some(States::State) = state.last() {
+                            // In place of a set, '^' is the start-of-input pseudo-set
+                            stack.push(Token::Identifier(loc.clone(), String::from('^')));
+                        }
+                        else if let Some(States::Lookahead) = state.last() {
+                            symbols.errors.push(Box::new(ParserError::StartAhead(loc.clone())));
+                        }
                         else {
-                            symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), format!("'^'")))); 
+                            symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from("'^'")))); 
                         }
                     },
                     Token::At(loc) => {
-                        stack.push(Token::Identifier(loc.clone(), String::from('@')));
+                        if let (Some(States::Lookahead), Some(cond)) = (state.last(), condition.as_mut()) {
+                            cond.sets.push(String::from('@'));
+                        }
+                        else {
+                            stack.push(Token::Identifier(loc.clone(), String::from('@')));
+                        }
+                    },
+                    Token::Dollar(loc) => {
+                        if let (Some(States::Lookahead), Some(cond)) = (state.last(), condition.as_mut()) {
+                            cond.sets.push(String::from('$'));
+                        }
+                        else {
+                            stack.push(Token::Identifier(loc.clone(), String::from('$')));
+                        }
+                    },
+                    Token::Ampersand(loc) | Token::Bang(loc) => {
+                        if let (Some(States::State), Some(Token::Identifier(_, _)), None) = (state.last(), stack.last(), &condition) {
+                            condition = Some(Condition {
+                                negated: matches!(tok, Token::Bang(_)),
+                                sets: Vec::new()
+                            });
+                        }
+                        else {
+                            symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), format!("'{}'", if matches!(tok, Token::Bang(_)) { '!' } else { '&' })))); 
+                        }
                     },
                     Token::Dot(loc) => {
                         if let Some(States::Transition) = state.last() {
                             stack.push(Token::Identifier(loc.clone(), String::from('.')));
                         }
                         else {
-                            symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), format!("'.'")))); 
+                            symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from("'.'")))); 
                         }
                     },
-                    Token::Pipe(loc) => todo!(),
                     Token::Percent(loc) => {
                         {
                             if let Some(States::Transition) = state.last() {
                                 if writing_behaviour != WritingBehaviour::WriteAfter {
-                                    symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), format!("'%'")))); 
+                                    symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from("'%'")))); 
                                 }
                                 else {
                                     writing_behaviour = WritingBehaviour::WriteBefore;
                                 }
                             }
                             else {
-                                symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), format!("'%'")))); 
+                                symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from("'%'")))); 
                             }
                         }
                     },
+                    Token::Greater(loc) | Token::Less(loc) => {
+                        let (behaviour, specifier) = match tok {
+                            Token::Greater(_) => (StackBehaviour::Push, "'>'"),
+                            _ => (StackBehaviour::Pop, "'<'")
+                        };
+
+                        if let (Some(States::Transition), StackBehaviour::Keep) = (state.last(), stack_behaviour) {
+                            stack_behaviour = behaviour;
+                        }
+                        else {
+                            symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from(specifier)))); 
+                        }
+                    },
                     Token::Error => todo!(),
-                    Token::SemiColon(loc) => {
+                    Token::Pipe(_) | Token::SemiColon(_) if state.last() == Some(&States::Transition) => {
 
-                        println!("{stack:?}");
-                        if let Some(States::Transition) = state.last() {
+                        if verbose {
+                            println!("{stack:?}");
+                        }
 
-                            if let (Some(Token::Identifier(_, target)), 
-                                Some(Token::Identifier(loc_or, origin)),
-                                Some(Token::Identifier(loc_cur, current_state)),
-                                ) = (stack.pop(), stack.pop(), stack.last()) {
-
-                                    if let Some(obj) = symbols.objects.get_mut(current_state) {
-                                        for before in obj.transitions.iter().filter(|x| x.1 == origin) {
-                                            // If there's a duplicate source
-                                            
-                                            symbols.errors.push(Box::new(ParserError::DuplicateTransition(loc_or.clone(), format!("{origin} => {target} ({}already defined here)", before.0)))); 
-                                        }
-                                        obj.transitions.push((loc_or, origin, target, writing_behaviour));
-                                        state.pop();
-                                        writing_behaviour = WritingBehaviour::WriteAfter;
-                                    }
-                                    else {
-                                        //TODO: change error type?
-                                        symbols.errors.push(Box::new(ParserError::UndefinedIdentifier(loc_cur.clone(), current_state.to_owned()))); 
+                        // Each alternative separated by a pipe is a transition of its own, sharing the set and its conditions
+                        let last = matches!(tok, Token::SemiColon(_));
+
+                        if let (Some(Token::Identifier(_, target)), 
+                            Some(Token::Identifier(loc_or, origin)),
+                            Some(Token::Identifier(loc_cur, current_state)),
+                            ) = (stack.pop(), stack.last().cloned(), stack.iter().rev().nth(1)) {
+
+                                if let Some(obj) = symbols.objects.get_mut(current_state) {
+                                    let previous = obj.transitions.len() - alternatives;
+                                    for before in obj.transitions[..previous].iter().filter(|x| x.origin == origin && x.conditions == conditions) {
+                                        // If there's a duplicate source
+                                        
+                                        symbols.errors.push(Box::new(ParserError::DuplicateTransition(loc_or.clone(), format!("{origin} => {target} ({}already defined here)", before.loc)))); 
                                     }
+                                    obj.transitions.push(Transition {
+                                        loc: loc_or, 
+                                        origin, 
+                                        target, 
+                                        behaviour: writing_behaviour, 
+                                        stack: stack_behaviour,
+                                        conditions: conditions.clone(),
+                                        rewrite: rewrite.clone(),
+                                        attributes: std::mem::take(&mut attributes),
+                                        keep_node: false
+                                    });
+                                    writing_behaviour = WritingBehaviour::WriteAfter;
+                                    stack_behaviour = StackBehaviour::Keep;
+                                    alternatives += 1;
                                 }
+                                else {
+                                    //TODO: change error type?
+                                    symbols.errors.push(Box::new(ParserError::UndefinedIdentifier(loc_cur.clone(), current_state.to_owned()))); 
+                                }
+                            }
 
-                        }
-                        else {
+                        if last {
                             stack.pop();
+                            state.pop();
+                            conditions.clear();
+                            rewrite = None;
+                            alternatives = 0;
                         }
                     },
+                    Token::Pipe(loc) => {
+                        symbols.errors.push(Box::new(ParserError::NotAttached(loc.clone(), String::from("'|'")))); 
+                    },
+                    Token::SemiColon(_) => {
+                        stack.pop();
+                    },
                 }
             }
             else if verbose {
@@ -334,6 +706,10 @@ impl Symbols {
             }
         }
 
+        if let Some(test) = test {
+            symbols.errors.push(Box::new(ParserError::Unclosed(test.loc, String::from("test"))));
+        }
+
         if !stack.is_empty() {
             while let Some(token) = stack.pop() {
                 match token {
@@ -343,16 +719,18 @@ impl Symbols {
             }
         }
 
-        for obj in symbols.objects.iter() {
-            println!("{}", serde_json::to_string_pretty(&obj).unwrap());
-        }
+        if verbose {
+            for obj in symbols.objects.iter() {
+                println!("{}", serde_json::to_string_pretty(&obj).unwrap());
+            }
 
-        for (i, val) in symbols.values.iter().enumerate() {
-            println!("{i}: {val:?}");
-        }
+            for (i, val) in symbols.values.iter().enumerate() {
+                println!("{i}: {val:?}");
+            }
 
-        for error in symbols.errors.iter() {
-            print!("{error}");
+            for error in symbols.errors.iter() {
+                print!("{error}");
+            }
         }
 
         symbols
@@ -0,0 +1,54 @@
+use super::interpreter::*;
+use super::nfa::{Ambiguity, Policy};
+
+// Machines chained so that the nodes written by one are the units read by the next
+#[derive(Default)]
+pub struct Pipeline<'a> {
+    stages: Vec<Interpreter<'a>>
+}
+
+impl<'a> Pipeline<'a> {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stage(mut self, stage: Interpreter<'a>) -> Self {
+        self.stages.push(stage);
+        self
+    }
+
+    pub fn run(&self, input: &str) -> Result<Vec<Node>, InterpreterError> {
+
+        let mut stages = self.stages.iter();
+
+        let Some(first) = stages.next() else {
+            return Ok(Vec::new());
+        };
+
+        let mut nodes = first.run(input)?;
+        for stage in stages {
+            nodes = stage.run(nodes.as_slice())?;
+        }
+
+        Ok(nodes)
+    }
+
+    pub fn simulate(&self, input: &str, policy: Policy) -> Result<(Vec<Node>, Vec<Ambiguity>), InterpreterError> {
+
+        let mut stages = self.stages.iter();
+
+        let Some(first) = stages.next() else {
+            return Ok((Vec::new(), Vec::new()));
+        };
+
+        let (mut nodes, mut ambiguities) = first.simulate(input, policy)?;
+        for stage in stages {
+            let (next, mut found) = stage.simulate(nodes.as_slice(), policy)?;
+            nodes = next;
+            ambiguities.append(&mut found);
+        }
+
+        Ok((nodes, ambiguities))
+    }
+}
@@ -0,0 +1,195 @@
+use std::{collections::HashMap, fmt::{Display, Write}, time::{Duration, Instant}};
+
+use super::diff::{describe, on};
+use super::interpreter::*;
+use super::parser::*;
+use super::scanner::Pos;
+
+#[derive(Debug, Default, Clone)]
+pub struct StateProfile {
+    pub id: String,
+    pub entries: usize,
+    pub tokens: usize, // Non-empty tokens read
+    pub bytes: usize,
+    pub fallbacks: usize, // Tokens read through '@', none of the sets of the state matching
+    pub time: Duration
+}
+
+impl StateProfile {
+    pub fn average_length(&self) -> f64 {
+        if self.tokens == 0 { 0.0 } else { self.bytes as f64 / self.tokens as f64 }
+    }
+}
+
+// Counts and time spent in the states and transitions of the runs it observed
+// Time is measured between two events, and goes to the state current in between
+#[derive(Debug)]
+pub struct Profile {
+    states: HashMap<Pos, StateProfile>,
+    transitions: HashMap<(Pos, String), (String, usize)>,
+    sets: HashMap<(String, String), usize>, // By file, as called machines may have sets of the same name
+    folded: HashMap<String, Duration>,
+    // States that pushed or called, with the length of the stack when each call started
+    stack: Vec<String>,
+    calls: Vec<usize>,
+    current: Option<(Pos, String)>,
+    last: Instant
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            states: HashMap::new(),
+            transitions: HashMap::new(),
+            sets: HashMap::new(),
+            folded: HashMap::new(),
+            stack: Vec::new(),
+            calls: Vec::new(),
+            current: None,
+            last: Instant::now()
+        }
+    }
+}
+
+impl Profile {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Gives the time since the last event to the current state
+    fn tick(&mut self) {
+
+        let now = Instant::now();
+        let elapsed = now - self.last;
+        self.last = now;
+
+        if let Some((loc, id)) = &self.current {
+            self.states.get_mut(loc).unwrap().time += elapsed;
+            let mut stack = self.stack.join(";");
+            if !stack.is_empty() {
+                stack.push(';');
+            }
+            stack.push_str(id);
+            *self.folded.entry(stack).or_default() += elapsed;
+        }
+    }
+
+    fn state(&mut self, state: &Object) -> &mut StateProfile {
+        self.states.entry(state.loc.clone()).or_insert_with(|| StateProfile { id: state.id.clone(), ..Default::default() })
+    }
+
+    // States by decreasing time spent in them
+    pub fn states(&self) -> Vec<&StateProfile> {
+        let mut out: Vec<&StateProfile> = self.states.values().collect();
+        out.sort_by(|a, b| b.time.cmp(&a.time).then(b.entries.cmp(&a.entries)).then(a.id.cmp(&b.id)));
+        out
+    }
+
+    // Transitions as written in the rule file, by decreasing firings
+    pub fn transitions(&self) -> Vec<(&str, usize)> {
+        let mut out: Vec<(&str, usize)> = self.transitions.values().map(|(x, n)| (x.as_str(), *n)).collect();
+        out.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        out
+    }
+
+    // Sets with their file, by decreasing number of tokens read through them
+    pub fn sets(&self) -> Vec<(&str, &str, usize)> {
+        let mut out: Vec<(&str, &str, usize)> = self.sets.iter().map(|((file, x), n)| (file.as_str(), x.as_str(), *n)).collect();
+        out.sort_by(|a, b| b.2.cmp(&a.2).then(a.1.cmp(b.1)).then(a.0.cmp(b.0)));
+        out
+    }
+
+    // One line per stack of states, with the nanoseconds spent there, as read by flamegraph tools
+    // Frames are the states that pushed a level or called a machine, then the current state
+    pub fn folded(&self) -> String {
+        let mut stacks: Vec<(&String, &Duration)> = self.folded.iter().collect();
+        stacks.sort();
+        stacks.into_iter().fold(String::new(), |mut out, (stack, time)| {
+            writeln!(out, "{} {}", stack, time.as_nanos()).unwrap();
+            out
+        })
+    }
+}
+
+impl Observer for Profile {
+
+    fn enter(&mut self, state: &Object) {
+        self.tick();
+        self.state(state).entries += 1;
+        self.current = Some((state.loc.clone(), state.id.clone()));
+    }
+
+    fn fire(&mut self, state: &Object, transition: &Transition, text: &str) {
+
+        self.tick();
+
+        let profile = self.state(state);
+        if !text.is_empty() {
+            profile.tokens += 1;
+            profile.bytes += text.len();
+        }
+        if transition.origin == "@" {
+            profile.fallbacks += 1;
+        }
+
+        self.transitions.entry((transition.loc.clone(), transition.target.clone()))
+            .or_insert_with(|| (format!("{}: {}{}", state.id, on(transition), describe(transition)), 0)).1 += 1;
+
+        if !PSEUDO_SETS.contains(&transition.origin.as_str()) {
+            *self.sets.entry((transition.loc.file.clone(), transition.origin.clone())).or_default() += 1;
+        }
+
+        // Levels the machine opens are frames of their own, as long as they stay open
+        match transition.stack {
+            StackBehaviour::Push => self.stack.push(state.id.clone()),
+            StackBehaviour::Pop if self.stack.len() > self.calls.last().copied().unwrap_or(0) => { self.stack.pop(); },
+            _ => {}
+        }
+    }
+
+    fn call(&mut self, state: &Object) {
+        self.tick();
+        self.calls.push(self.stack.len());
+        self.stack.push(state.id.clone());
+        self.current = None;
+    }
+
+    fn back(&mut self, state: &Object) {
+        self.tick();
+        if let Some(len) = self.calls.pop() {
+            self.stack.truncate(len);
+        }
+        self.state(state);
+        self.current = Some((state.loc.clone(), state.id.clone()));
+    }
+
+    fn done(&mut self) {
+        self.tick();
+        self.stack.clear();
+        self.calls.clear();
+        self.current = None;
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+
+        writeln!(f, "{:<24} {:>9} {:>9} {:>8} {:>9} {:>12}", "state", "entries", "tokens", "length", "fallbacks", "time (µs)")?;
+        for x in self.states() {
+            writeln!(f, "{:<24} {:>9} {:>9} {:>8.2} {:>9} {:>12.1}", x.id, x.entries, x.tokens, x.average_length(), x.fallbacks, x.time.as_secs_f64() * 1e6)?;
+        }
+
+        writeln!(f, "\n{:<24} {:>9}  file", "set", "reads")?;
+        for (file, set, reads) in self.sets() {
+            writeln!(f, "{:<24} {:>9}  {}", set, reads, file)?;
+        }
+
+        writeln!(f, "\n{:>9}  transition", "firings")?;
+        for (transition, firings) in self.transitions() {
+            writeln!(f, "{:>9}  {}", firings, transition)?;
+        }
+
+        Ok(())
+    }
+}
@@ -25,11 +25,11 @@ impl Display for ScannerError {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct Pos {
-    file: String,
-    column: usize,
-    line: usize
+    pub(crate) file: String,
+    pub(crate) column: usize,
+    pub(crate) line: usize
 }
 
 impl Display for Pos {
@@ -45,6 +45,8 @@ pub enum Token {
     CloseParen(Pos),
     OpenBrackets(Pos),
     CloseBrackets(Pos),
+    OpenSquare(Pos),
+    CloseSquare(Pos),
     Identifier(Pos, String),
     Litteral(Pos, String),
     Equal(Pos),
@@ -52,9 +54,19 @@ pub enum Token {
     Colon(Pos),
     Hat(Pos),
     At(Pos),
+    Dollar(Pos),
     Dot(Pos),
     Pipe(Pos),
     Percent(Pos),
+    Ampersand(Pos),
+    Bang(Pos),
+    Arrow(Pos),
+    Implies(Pos),
+    Greater(Pos),
+    Less(Pos),
+    Call(Pos),
+    Test(Pos),
+    Comma(Pos),
     SemiColon(Pos),
     Error
 
@@ -68,6 +80,8 @@ impl Display for Token {
             Token::CloseParen(a) => write!(f, "{} )", a),
             Token::OpenBrackets(a) => write!(f, "{} {{", a),
             Token::CloseBrackets(a) => write!(f, "{} }}", a),
+            Token::OpenSquare(a) => write!(f, "{} [", a),
+            Token::CloseSquare(a) => write!(f, "{} ]", a),
             Token::Identifier(a, b) => write!(f, "{}ID[{:?}]", a, b),
             Token::Litteral(a, b) => write!(f, "{}LITT[{:?}]", a, b),
             Token::Equal(a) => write!(f, "{} =", a),
@@ -75,9 +89,19 @@ impl Display for Token {
             Token::Colon(a) => write!(f, "{} :", a),
             Token::Hat(a) => write!(f, "{} ^", a),
             Token::At(a) => write!(f, "{} @", a),
+            Token::Dollar(a) => write!(f, "{} $", a),
             Token::Dot(a) => write!(f, "{} .", a),
             Token::Pipe(a) => write!(f, "{} |", a),
             Token::Percent(a) => write!(f, "{} %", a),
+            Token::Ampersand(a) => write!(f, "{} &", a),
+            Token::Bang(a) => write!(f, "{} !", a),
+            Token::Arrow(a) => write!(f, "{} ->", a),
+            Token::Implies(a) => write!(f, "{} =>", a),
+            Token::Greater(a) => write!(f, "{} >", a),
+            Token::Less(a) => write!(f, "{} <", a),
+            Token::Call(a) => write!(f, "{} CALL", a),
+            Token::Test(a) => write!(f, "{} TEST", a),
+            Token::Comma(a) => write!(f, "{} ,", a),
             Token::SemiColon(a) => write!(f, "{} ;", a),
             Token::Error => write!(f, "ERROR")
         }
@@ -156,8 +180,11 @@ impl<'a> Iterator for Scanner<'a> {
                     }
 
                     // Reserved words check could go here
-
-                    Some(Ok(Token::Identifier(location, buffer)))
+                    match buffer.as_str() {
+                        "call" => Some(Ok(Token::Call(location))),
+                        "test" => Some(Ok(Token::Test(location))),
+                        _ => Some(Ok(Token::Identifier(location, buffer)))
+                    }
                 },
                 '\'' | '"' => {
                     let mut buffer = String::new();
@@ -205,17 +232,35 @@ impl<'a> Iterator for Scanner<'a> {
                 },
                 '{' => Some(Ok(Token::OpenBrackets(self.loc.clone()))),
                 '}' => Some(Ok(Token::CloseBrackets(self.loc.clone()))),
+                '[' => Some(Ok(Token::OpenSquare(self.loc.clone()))),
+                ']' => Some(Ok(Token::CloseSquare(self.loc.clone()))),
                 '(' => Some(Ok(Token::OpenParen(self.loc.clone()))),
                 ')' => Some(Ok(Token::CloseParen(self.loc.clone()))),
+                '=' if self.chars.next_if_eq(&'>').is_some() => {
+                    let location = self.loc.clone();
+                    self.loc.column += 1;
+                    Some(Ok(Token::Implies(location)))
+                },
                 '=' => Some(Ok(Token::Equal(self.loc.clone()))),
                 '*' => Some(Ok(Token::Star(self.loc.clone()))),
                 ':' => Some(Ok(Token::Colon(self.loc.clone()))),
                 '^' => Some(Ok(Token::Hat(self.loc.clone()))),
                 '@' => Some(Ok(Token::At(self.loc.clone()))),
+                '$' => Some(Ok(Token::Dollar(self.loc.clone()))),
                 '.' => Some(Ok(Token::Dot(self.loc.clone()))),
                 '|' => Some(Ok(Token::Pipe(self.loc.clone()))),
                 '%' => Some(Ok(Token::Percent(self.loc.clone()))),
+                '&' => Some(Ok(Token::Ampersand(self.loc.clone()))),
+                '!' => Some(Ok(Token::Bang(self.loc.clone()))),
                 ';' => Some(Ok(Token::SemiColon(self.loc.clone()))),
+                ',' => Some(Ok(Token::Comma(self.loc.clone()))),
+                '-' if self.chars.next_if_eq(&'>').is_some() => {
+                    let location = self.loc.clone();
+                    self.loc.column += 1;
+                    Some(Ok(Token::Arrow(location)))
+                },
+                '>' => Some(Ok(Token::Greater(self.loc.clone()))),
+                '<' => Some(Ok(Token::Less(self.loc.clone()))),
                 _ =>  {
                     Some(Err(ScannerError::UnknownToken(self.loc.clone(), c.to_string())))
                 }
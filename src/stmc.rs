@@ -0,0 +1,436 @@
+use std::{borrow::Cow, error::Error, fmt::Display, fs, path::Path};
+
+use super::compile::*;
+use super::parser::*;
+
+// Layout of the .stmc files, described in the README; any change to it needs a new version
+const MAGIC: &[u8; 4] = b"STMC";
+pub const VERSION: u32 = 2;
+const HEADER: usize = 24;
+
+// Called machines are read recursively; deeper files are rejected rather than overflowing the stack
+const MAX_NESTING: usize = 64;
+
+// Origins other than sets, and missing indices
+const NONE: u32 = u32::MAX;
+const ANY: u32 = u32::MAX - 2;
+const START: u32 = u32::MAX - 1;
+const END: u32 = u32::MAX;
+
+#[derive(Debug)]
+pub enum StmcError {
+    Io(String),
+    NotAMachine,
+    Version(u32),
+    Checksum(u32, u32),
+    Truncated(usize),
+    Invalid(usize, String)
+}
+
+impl Error for StmcError {}
+
+impl Display for StmcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+
+        match self {
+            StmcError::Io(e) => writeln!(f, "{}", e),
+            StmcError::NotAMachine => writeln!(f, "Not a compiled machine: the file does not start with {:?}", std::str::from_utf8(MAGIC).unwrap()),
+            StmcError::Version(version) => writeln!(f, "Compiled machine in format version {}, while version {} is expected: the rules need to be compiled again", version, VERSION),
+            StmcError::Checksum(expected, found) => writeln!(f, "Checksum mismatch ({:08x} expected, {:08x} found): the file is corrupted", expected, found),
+            StmcError::Truncated(at) => writeln!(f, "stmc:{:0>3} --> Unexpected end of the file", at),
+            StmcError::Invalid(at, what) => writeln!(f, "stmc:{:0>3} --> {}", at, what)
+        }
+    }
+}
+
+// CRC-32 (IEEE), as in zip or PNG files
+const CRC: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+            k += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &x| CRC[((crc ^ x as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+#[derive(Default)]
+struct Writer {
+    out: Vec<u8>
+}
+
+impl Writer {
+
+    fn u8(&mut self, x: u8) {
+        self.out.push(x);
+    }
+
+    fn u32(&mut self, x: u32) {
+        self.out.extend(x.to_le_bytes());
+    }
+
+    fn u64(&mut self, x: u64) {
+        self.out.extend(x.to_le_bytes());
+    }
+
+    fn u128(&mut self, x: u128) {
+        self.out.extend(x.to_le_bytes());
+    }
+
+    fn str(&mut self, x: &str) {
+        self.u32(x.len() as u32);
+        self.out.extend(x.as_bytes());
+    }
+
+    fn list<T>(&mut self, items: &[T], mut item: impl FnMut(&mut Self, &T)) {
+        self.u32(items.len() as u32);
+        for x in items {
+            item(self, x);
+        }
+    }
+
+    fn pairs(&mut self, pairs: &[(Cow<'_, str>, Cow<'_, str>)]) {
+        self.list(pairs, |w, (k, v)| { w.str(k); w.str(v); });
+    }
+
+    fn origin(&mut self, origin: &Origin) {
+        self.u32(match origin {
+            Origin::Set(id) => *id,
+            Origin::Any => ANY,
+            Origin::Start => START,
+            Origin::End => END
+        });
+    }
+
+    fn machine(&mut self, machine: &CompiledMachine) {
+
+        self.u32(machine.entry);
+        self.u64(machine.max_depth as u64);
+
+        // Sets are stored as their tables, the longer litterals being kept sorted so that they are searched in place
+        self.u32(machine.sets.len() as u32);
+        for (set, name) in machine.sets.iter().zip(machine.names.sets.iter()) {
+            self.str(name);
+            self.u128(set.ascii);
+            self.list(&set.ranges, |w, &(start, end)| { w.u32(start as u32); w.u32(end as u32); });
+            self.u8(set.empty as u8);
+            self.u32(set.multi.len() as u32);
+            self.out.extend(set.multi.ends.iter());
+            self.str(&set.multi.text);
+            self.list(&set.labels, |w, x| w.str(x));
+        }
+
+        self.u32(machine.states.len() as u32);
+        for (state, name) in machine.states.iter().zip(machine.names.states.iter()) {
+            self.str(name);
+            self.str(&state.label);
+            self.u8(state.end_point as u8);
+            self.pairs(&state.attributes);
+            self.u32(state.call.unwrap_or(NONE));
+            self.list(&state.transitions, |w, tr| {
+                w.origin(&tr.origin);
+                w.u32(tr.target.unwrap_or(NONE));
+                w.u8(match tr.behaviour {
+                    WritingBehaviour::WriteAfter => 0,
+                    WritingBehaviour::WriteBefore => 1,
+                    WritingBehaviour::NoWrite => 2
+                });
+                w.u8(match tr.stack {
+                    StackBehaviour::Keep => 0,
+                    StackBehaviour::Push => 1,
+                    StackBehaviour::Pop => 2
+                });
+                w.u8(tr.keep_node as u8);
+                w.u8(tr.rewrite.is_some() as u8);
+                w.str(tr.rewrite.as_deref().unwrap_or(""));
+                w.pairs(&tr.attributes);
+                w.list(&tr.conditions, |w, cond| {
+                    w.u8(cond.negated as u8);
+                    w.list(&cond.sets, |w, x| w.origin(x));
+                });
+            });
+        }
+
+        self.list(&machine.machines, |w, x| w.machine(x));
+    }
+}
+
+// Reads from a slice of bytes, e.g. a file mapped in memory; offsets in errors are from the start of the file
+// Text and the litterals of sets are borrowed from the bytes
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    depth: usize // Machines calling the one being read
+}
+
+impl<'a> Reader<'a> {
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StmcError> {
+        let out = self.bytes.get(self.pos..self.pos.saturating_add(len)).ok_or(StmcError::Truncated(self.bytes.len()))?;
+        self.pos += len;
+        Ok(out)
+    }
+
+    fn u8(&mut self) -> Result<u8, StmcError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, StmcError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, StmcError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn u128(&mut self) -> Result<u128, StmcError> {
+        Ok(u128::from_le_bytes(self.take(16)?.try_into().unwrap()))
+    }
+
+    fn bool(&mut self) -> Result<bool, StmcError> {
+        let at = self.pos;
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            x => Err(StmcError::Invalid(at, format!("Expected 0 or 1, found {}", x)))
+        }
+    }
+
+    fn str(&mut self) -> Result<&'a str, StmcError> {
+        let len = self.u32()? as usize;
+        let at = self.pos;
+        let bytes = self.take(len)?;
+        std::str::from_utf8(bytes).map_err(|_| StmcError::Invalid(at, String::from("Invalid UTF-8 text")))
+    }
+
+    fn text(&mut self) -> Result<Cow<'a, str>, StmcError> {
+        self.str().map(Cow::Borrowed)
+    }
+
+    fn char(&mut self) -> Result<char, StmcError> {
+        let at = self.pos;
+        let x = self.u32()?;
+        char::from_u32(x).ok_or(StmcError::Invalid(at, format!("No character {:#x}", x)))
+    }
+
+    // Ranges of characters are searched by halves, so they must be sorted and apart
+    fn ranges(&mut self) -> Result<Vec<(char, char)>, StmcError> {
+        let at = self.pos;
+        let ranges = self.list(|r| Ok((r.char()?, r.char()?)))?;
+        if ranges.iter().any(|(start, end)| start > end) || ranges.windows(2).any(|x| x[0].1 >= x[1].0) {
+            return Err(StmcError::Invalid(at, String::from("Ranges of characters are not sorted")));
+        }
+        Ok(ranges)
+    }
+
+    // Longer litterals are searched by halves in place, so they must be sorted and cut at characters
+    fn litterals(&mut self) -> Result<Litterals<'a>, StmcError> {
+        let count = self.u32()? as usize;
+        let ends = self.take(count.saturating_mul(4))?;
+        let at = self.pos;
+        let text = self.str()?;
+
+        let litterals = Litterals { ends: Cow::Borrowed(ends), text: Cow::Borrowed(text) };
+        let mut start = 0;
+        for i in 0..count {
+            let end = u32::from_le_bytes(ends[i * 4..i * 4 + 4].try_into().unwrap()) as usize;
+            if end <= start || !text.is_char_boundary(end) {
+                return Err(StmcError::Invalid(at, format!("Litteral {} is not in the text of the set", i)));
+            }
+            if i > 0 && litterals.get(i - 1) >= litterals.get(i) {
+                return Err(StmcError::Invalid(at, String::from("Litterals are not sorted")));
+            }
+            start = end;
+        }
+        if start != text.len() {
+            return Err(StmcError::Invalid(at, String::from("Unexpected text after the litterals")));
+        }
+
+        Ok(litterals)
+    }
+
+    // Counts are not trusted to reserve memory, items are read one by one
+    fn list<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T, StmcError>) -> Result<Vec<T>, StmcError> {
+        let count = self.u32()?;
+        (0..count).map(|_| item(self)).collect()
+    }
+
+    fn pairs(&mut self) -> Result<Attributes<'a>, StmcError> {
+        self.list(|r| Ok((r.text()?, r.text()?)))
+    }
+
+    fn index(&mut self, count: usize, what: &str) -> Result<Option<u32>, StmcError> {
+        let at = self.pos;
+        match self.u32()? {
+            NONE => Ok(None),
+            x if (x as usize) < count => Ok(Some(x)),
+            x => Err(StmcError::Invalid(at, format!("No {} {}, there are {}", what, x, count)))
+        }
+    }
+
+    fn origin(&mut self, sets: usize) -> Result<Origin, StmcError> {
+        let at = self.pos;
+        match self.u32()? {
+            ANY => Ok(Origin::Any),
+            START => Ok(Origin::Start),
+            END => Ok(Origin::End),
+            x if (x as usize) < sets => Ok(Origin::Set(x)),
+            x => Err(StmcError::Invalid(at, format!("No set {}, there are {}", x, sets)))
+        }
+    }
+
+    fn machine(&mut self) -> Result<CompiledMachine<'a>, StmcError> {
+
+        if self.depth == MAX_NESTING {
+            return Err(StmcError::Invalid(self.pos, format!("Machines called more than {} levels deep", MAX_NESTING)));
+        }
+
+        let entry_at = self.pos;
+        let entry = self.u32()?;
+        let max_depth = self.u64()? as usize;
+
+        let mut names = Names::default();
+        let sets = self.list(|r| {
+            names.sets.push(r.text()?);
+            let ascii = r.u128()?;
+            let ranges = r.ranges()?;
+            let empty = r.bool()?;
+            let multi = r.litterals()?;
+            let labels = r.list(Reader::text)?;
+            Ok(CompiledSet::with(ascii, ranges, empty, multi, labels))
+        })?;
+
+        let count = self.u32()? as usize;
+        let mut states = Vec::new();
+        let mut calls = Vec::new();
+
+        for _ in 0..count {
+            names.states.push(self.text()?);
+            let label = self.text()?;
+            let end_point = self.bool()?;
+            let attributes = self.pairs()?;
+            calls.push(self.pos);
+            let call = self.u32()?;
+
+            let transitions = self.list(|r| {
+                let origin = r.origin(sets.len())?;
+                let target = r.index(count, "state")?;
+                let at = r.pos;
+                let behaviour = match r.u8()? {
+                    0 => WritingBehaviour::WriteAfter,
+                    1 => WritingBehaviour::WriteBefore,
+                    2 => WritingBehaviour::NoWrite,
+                    x => return Err(StmcError::Invalid(at, format!("Unknown writing behaviour {}", x)))
+                };
+                let at = r.pos;
+                let stack = match r.u8()? {
+                    0 => StackBehaviour::Keep,
+                    1 => StackBehaviour::Push,
+                    2 => StackBehaviour::Pop,
+                    x => return Err(StmcError::Invalid(at, format!("Unknown stack behaviour {}", x)))
+                };
+                let keep_node = r.bool()?;
+                let rewrite = if r.bool()? { Some(r.text()?) } else { r.str()?; None };
+                let attributes = r.pairs()?;
+                let conditions = r.list(|r| Ok(CompiledCondition { negated: r.bool()?, sets: r.list(|r| r.origin(sets.len()))? }))?;
+
+                Ok(CompiledTransition { origin, target, behaviour, stack, conditions, rewrite, attributes, keep_node })
+            })?;
+
+            states.push(CompiledState::new(label, end_point, attributes, (call != NONE).then_some(call), transitions));
+        }
+
+        self.depth += 1;
+        let machines = self.list(Reader::machine)?;
+        self.depth -= 1;
+
+        if entry as usize >= states.len() {
+            return Err(StmcError::Invalid(entry_at, format!("No state {}, there are {}", entry, states.len())));
+        }
+        for (at, call) in calls.into_iter().zip(states.iter()).filter_map(|(at, x)| x.call.map(|call| (at, call))) {
+            if call as usize >= machines.len() {
+                return Err(StmcError::Invalid(at, format!("No machine {}, there are {}", call, machines.len())));
+            }
+        }
+
+        Ok(CompiledMachine::build(states, sets, entry, machines, max_depth, names))
+    }
+}
+
+impl<'m> CompiledMachine<'m> {
+
+    // The machine in the .stmc format: a header with the version and a checksum, then its sets, states and called machines
+    pub fn to_bytes(&self) -> Vec<u8> {
+
+        let mut body = Writer::default();
+        body.machine(self);
+
+        let mut out = Writer::default();
+        out.out.extend(MAGIC);
+        out.u32(VERSION);
+        out.u64(body.out.len() as u64);
+        out.u32(crc32(&body.out));
+        out.u32(0);
+        out.out.extend(body.out);
+        out.out
+    }
+
+    // The machine borrows its text and the litterals of its sets from the bytes, which are only checked and not copied
+    pub fn from_bytes(bytes: &'m [u8]) -> Result<Self, StmcError> {
+
+        if !bytes.starts_with(MAGIC) {
+            return Err(StmcError::NotAMachine);
+        }
+
+        let mut reader = Reader { bytes, pos: MAGIC.len(), depth: 0 };
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(StmcError::Version(version));
+        }
+
+        let len = reader.u64()?;
+        let checksum = reader.u32()?;
+        reader.u32()?;
+
+        if bytes.len() as u64 - (HEADER as u64) < len {
+            return Err(StmcError::Truncated(bytes.len()));
+        }
+        if bytes.len() as u64 - (HEADER as u64) > len {
+            return Err(StmcError::Invalid(HEADER + len as usize, String::from("Unexpected data after the machine")));
+        }
+
+        let found = crc32(&bytes[HEADER..]);
+        if found != checksum {
+            return Err(StmcError::Checksum(checksum, found));
+        }
+
+        let machine = reader.machine()?;
+        if reader.pos != bytes.len() {
+            return Err(StmcError::Invalid(reader.pos, String::from("Unexpected data after the machine")));
+        }
+
+        Ok(machine)
+    }
+
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), StmcError> {
+        fs::write(path, self.to_bytes()).map_err(|e| StmcError::Io(e.to_string()))
+    }
+}
+
+impl CompiledMachine<'static> {
+
+    // The bytes of the file are not kept, so the machine owns a copy of what it would borrow from them
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, StmcError> {
+        CompiledMachine::from_bytes(&fs::read(path).map_err(|e| StmcError::Io(e.to_string()))?).map(CompiledMachine::into_owned)
+    }
+}
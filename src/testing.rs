@@ -0,0 +1,244 @@
+use std::{fmt::Display, fs, io, path::{Path, PathBuf}};
+
+use super::export;
+use super::interpreter::*;
+use super::parser::*;
+use super::pipeline::Pipeline;
+use super::scanner::Pos;
+
+// Step of the shortest edit from the expected tokens to the actual ones
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    Same(String),
+    Missing(String),
+    Unexpected(String)
+}
+
+impl Display for Edit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Edit::Same(token) => write!(f, "  {:?}", token),
+            Edit::Missing(token) => write!(f, "- {:?}", token),
+            Edit::Unexpected(token) => write!(f, "+ {:?}", token)
+        }
+    }
+}
+
+// Longest common subsequence of both token sequences, the other tokens being missing or unexpected
+pub fn diff_tokens(expected: &[String], actual: &[String]) -> Vec<Edit> {
+
+    let (n, m) = (expected.len(), actual.len());
+    let mut lengths = vec![vec![0; m + 1]; n + 1];
+
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i][j] = if expected[i] == actual[j] { lengths[i + 1][j + 1] + 1 } else { lengths[i + 1][j].max(lengths[i][j + 1]) };
+        }
+    }
+
+    let mut out = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && expected[i] == actual[j] {
+            out.push(Edit::Same(expected[i].clone()));
+            i += 1;
+            j += 1;
+        }
+        else if j == m || (i < n && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            out.push(Edit::Missing(expected[i].clone()));
+            i += 1;
+        }
+        else {
+            out.push(Edit::Unexpected(actual[j].clone()));
+            j += 1;
+        }
+    }
+
+    out
+}
+
+// Test of the rule file whose nodes were not the expected ones
+#[derive(Debug)]
+pub struct Failure {
+    pub loc: Pos,
+    pub input: String,
+    pub expected: Vec<String>,
+    pub actual: Result<Vec<String>, String>
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+
+        match &self.actual {
+            Ok(actual) => {
+                writeln!(f, "{}Test failed on {:?}", self.loc, self.input)?;
+                for edit in diff_tokens(&self.expected, actual) {
+                    writeln!(f, "    {}", edit)?;
+                }
+                Ok(())
+            },
+            Err(e) => write!(f, "{}Test failed on {:?}: {}", self.loc, self.input, e)
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TestReport {
+    pub passed: usize,
+    pub failures: Vec<Failure>
+}
+
+impl Display for TestReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for failure in self.failures.iter() {
+            write!(f, "{}", failure)?;
+        }
+        writeln!(f, "{} passed, {} failed", self.passed, self.failures.len())
+    }
+}
+
+impl Symbols {
+
+    // Runs the tests written in the rule file, comparing the text of the nodes with the expected one
+    pub fn run_tests(&self) -> Result<TestReport, InterpreterError> {
+
+        let interpreter = Interpreter::new(self)?;
+        let mut report = TestReport::default();
+
+        for test in self.tests.iter() {
+
+            let actual = interpreter.run(test.input.as_str())
+                .map(|nodes| nodes.into_iter().map(|x| x.text).collect::<Vec<_>>())
+                .map_err(|e| e.to_string());
+
+            if actual.as_ref() == Ok(&test.expected) {
+                report.passed += 1;
+            }
+            else {
+                report.failures.push(Failure {
+                    loc: test.loc.clone(),
+                    input: test.input.clone(),
+                    expected: test.expected.clone(),
+                    actual
+                });
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+// What became of an input file compared with its expected output
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Passed,
+    Blessed,
+    // No expected output yet
+    Missing,
+    // Edits from the expected lines to the actual ones, with the line of the expected output they apply to
+    Changed(Vec<(usize, Edit)>)
+}
+
+#[derive(Debug)]
+pub struct Snapshot {
+    pub input: PathBuf,
+    pub outcome: Outcome
+}
+
+impl Display for Snapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+
+        let input = self.input.display();
+        match &self.outcome {
+            Outcome::Passed => writeln!(f, "{}: ok", input),
+            Outcome::Blessed => writeln!(f, "{}: blessed", input),
+            Outcome::Missing => writeln!(f, "{}: no expected output, run with --bless to write it", input),
+            Outcome::Changed(edits) => {
+                writeln!(f, "{}: changed", input)?;
+                for (line, edit) in edits.iter() {
+                    let text = match edit {
+                        Edit::Same(x) => format!("  {}", x),
+                        Edit::Missing(x) => format!("- {}", x),
+                        Edit::Unexpected(x) => format!("+ {}", x)
+                    };
+                    writeln!(f, "    {:>4} {}", line, text)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// Output stored for an input: one node per line, or the error the machine stopped on
+pub fn render(pipeline: &Pipeline, input: &str) -> String {
+    match pipeline.run(input) {
+        Ok(nodes) => export::to_text(&nodes),
+        Err(e) => e.to_string()
+    }
+}
+
+// Runs the machine over every file of the directory and compares the output with the one stored next to it, in '<file>.expected'
+// When blessing, the stored outputs are written over instead
+pub fn snapshots(pipeline: &Pipeline, dir: &Path, bless: bool) -> io::Result<Vec<Snapshot>> {
+
+    let mut inputs: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|x| x.map(|x| x.path()))
+        .collect::<io::Result<_>>()?;
+    inputs.retain(|x| x.is_file() && x.extension().is_none_or(|x| x != "expected"));
+    inputs.sort();
+
+    let mut out = Vec::new();
+
+    for input in inputs {
+
+        let actual = render(pipeline, &fs::read_to_string(&input)?);
+        let mut path = input.clone().into_os_string();
+        path.push(".expected");
+
+        let outcome = match fs::read_to_string(&path) {
+            Ok(expected) if expected == actual => Outcome::Passed,
+            _ if bless => {
+                fs::write(&path, &actual)?;
+                Outcome::Blessed
+            },
+            Ok(expected) => {
+                let (expected, actual): (Vec<String>, Vec<String>) = (expected.lines().map(String::from).collect(), actual.lines().map(String::from).collect());
+
+                // Only the lines that changed are kept
+                let mut line = 1;
+                let mut edits = Vec::new();
+                for edit in diff_tokens(&expected, &actual) {
+                    let at = line;
+                    if !matches!(edit, Edit::Unexpected(_)) {
+                        line += 1;
+                    }
+                    if !matches!(edit, Edit::Same(_)) {
+                        edits.push((at, edit));
+                    }
+                }
+                Outcome::Changed(edits)
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Outcome::Missing,
+            Err(e) => return Err(e)
+        };
+
+        out.push(Snapshot { input, outcome });
+    }
+
+    Ok(out)
+}
+
+// For test suites: panics with the differences unless every output is the expected one
+// Setting STAMESP_BLESS writes the outputs instead
+pub fn assert_snapshots(pipeline: &Pipeline, dir: impl AsRef<Path>) {
+
+    let bless = std::env::var_os("STAMESP_BLESS").is_some();
+    let snapshots = snapshots(pipeline, dir.as_ref(), bless).expect("Cannot read the snapshots");
+
+    let failures: String = snapshots.iter()
+        .filter(|x| matches!(x.outcome, Outcome::Changed(_) | Outcome::Missing))
+        .map(|x| x.to_string())
+        .collect();
+
+    assert!(failures.is_empty(), "Snapshots differ:\n{}", failures);
+}